@@ -0,0 +1,157 @@
+use std::{error::Error, fmt::Display};
+
+pub const USAGE: &str = "\
+Usage: md [OPTIONS] [FILE]...
+
+Shows Markdown files in the terminal. With no FILE, or when FILE is -, reads standard input.
+When the output isn't a terminal, prints the whole document instead of paging it.
+
+Options:
+      --width <COLUMNS>  Use at most COLUMNS of the terminal. When the output isn't a
+                         terminal, print COLUMNS wide [default: $COLUMNS, or else 80]
+      --no-color         Don't use colors, only bold, italic and underlined text
+      --plain            Don't style the text at all
+      --no-hyperlinks    Don't make links clickable, for terminals that show the escape
+                         sequences as text
+  -h, --help             Print this help and exit
+  -V, --version          Print the version and exit
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    File(String),
+    Stdin,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub inputs: Vec<Input>,
+    pub width: Option<usize>,
+    pub color: bool,
+    pub plain: bool,
+    pub hyperlinks: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Show(Options),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CliError(String);
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CliError {}
+
+fn parse_width(value: Option<String>) -> Result<usize, CliError> {
+    let value = value.ok_or_else(|| CliError("--width needs a value".to_string()))?;
+    match value.parse() {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(CliError(format!("invalid width '{}'", value))),
+    }
+}
+
+/// Parses the arguments, without the name of the program.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut options = Options {
+        inputs: Vec::new(),
+        width: None,
+        color: true,
+        plain: false,
+        hyperlinks: true,
+    };
+    let mut args = args.into_iter();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files || arg == "-" || !arg.starts_with('-') {
+            options.inputs.push(if arg == "-" && !only_files {
+                Input::Stdin
+            } else {
+                Input::File(arg)
+            });
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_files = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--no-color" => options.color = false,
+            "--plain" => options.plain = true,
+            "--no-hyperlinks" => options.hyperlinks = false,
+            "--width" => options.width = Some(parse_width(args.next())?),
+            _ => match arg.strip_prefix("--width=") {
+                Some(value) => options.width = Some(parse_width(Some(value.to_string()))?),
+                None => return Err(CliError(format!("unknown option '{}'", arg))),
+            },
+        }
+    }
+    if options.inputs.is_empty() {
+        options.inputs.push(Input::Stdin);
+    }
+    Ok(Command::Show(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Show(options)) => options,
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn files_and_stdin() {
+        assert_eq!(options(&[]).inputs, vec![Input::Stdin]);
+        assert_eq!(
+            options(&["a.md", "-", "b.md"]).inputs,
+            vec![
+                Input::File("a.md".to_string()),
+                Input::Stdin,
+                Input::File("b.md".to_string())
+            ]
+        );
+        assert_eq!(
+            options(&["--", "--plain", "-"]).inputs,
+            vec![
+                Input::File("--plain".to_string()),
+                Input::File("-".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let parsed = options(&["--width", "60", "--no-color", "a.md", "--plain"]);
+        assert_eq!(parsed.width, Some(60));
+        assert!(!parsed.color);
+        assert!(parsed.plain);
+        assert!(parsed.hyperlinks);
+        assert!(!options(&["--no-hyperlinks"]).hyperlinks);
+        assert_eq!(options(&["--width=72"]).width, Some(72));
+
+        assert_eq!(parse(&["a.md", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse(&["--width"]).is_err());
+        assert!(parse(&["--width", "0"]).is_err());
+        assert!(parse(&["--width=wide"]).is_err());
+        assert!(parse(&["--colour"]).is_err());
+    }
+}
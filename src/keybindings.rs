@@ -18,7 +18,7 @@ pub enum KeybindingResoluton<'a, Output> {
 }
 
 impl<Output> Keybindings<Output> {
-    pub fn resolve_keys(&self, keys: &[Key]) -> KeybindingResoluton<Output> {
+    pub fn resolve_keys(&self, keys: &[Key]) -> KeybindingResoluton<'_, Output> {
         let matching_keybindings: Vec<_> = self
             .keybindings
             .iter()
@@ -50,7 +50,7 @@ pub struct KeybindingsBuilderError(String);
 
 impl Display for KeybindingsBuilderError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        f.write_str(&self.0)
     }
 }
 
@@ -69,7 +69,7 @@ impl<Output> KeybindingsBuilder<Output> {
         output: Output,
     ) -> Result<Self, KeybindingsBuilderError> {
         let keybinding = Keybinding {
-            keys: keys.iter().cloned().collect(),
+            keys: keys.to_vec(),
             output,
         };
         if self.keybindings.iter().any(|k| keybinding.keys == k.keys) {
@@ -113,7 +113,7 @@ mod tests {
         ] {
             let keybindings = KeybindingsBuilder::<TestKeybindingOutput>::new().build();
 
-            let output = keybindings.resolve_keys(&keys);
+            let output = keybindings.resolve_keys(keys);
 
             assert_eq!(output, KeybindingResoluton::NoKeybinding);
         }
@@ -148,11 +148,11 @@ mod tests {
             (vec!['j'.into(), 'k'.into()], TestKeybindingOutput::B),
         ] {
             let keybindings = KeybindingsBuilder::new()
-                .add_keybinding(&keys, output.clone())
+                .add_keybinding(keys, output)
                 .unwrap()
                 .build();
 
-            let res = keybindings.resolve_keys(&keys);
+            let res = keybindings.resolve_keys(keys);
 
             assert_eq!(res, KeybindingResoluton::Resolved(&output));
         }
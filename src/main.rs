@@ -1,21 +1,142 @@
-use std::fs;
+use std::{
+    env,
+    error::Error,
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal, Read},
+    process,
+};
 
-use markdown_parser::{Markdown, MarkdownElement};
-use terminal::TerminalCanvas;
+use cli::{Command, Input, Options};
+use keybindings::Keybindings;
+use markdown_parser::Markdown;
+use pager::Action;
+use renderer::{LayoutOptions, Renderer, Theme};
+use terminal::{TerminalCanvas, TerminalEvents};
 
 mod terminal;
 mod keybindings;
 mod markdown_parser;
+mod pager;
 mod renderer;
 mod common;
+mod cli;
 
-fn main() {
-    let text = fs::read_to_string("sample.short.md").unwrap();
-    let markdown = Markdown::parse(&text).unwrap();
-    let (mut terminal_canvas, terminal_events) = terminal::start().unwrap();
+fn usage_error(message: impl Display) -> ! {
+    eprintln!("md: {}", message);
+    eprintln!("Try 'md --help' for more information.");
+    process::exit(2);
+}
+
+fn read_input(input: &Input) -> Result<String, Box<dyn Error>> {
+    match input {
+        Input::File(path) => Ok(fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?),
+        Input::Stdin => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("standard input: {}", e))?;
+            Ok(text)
+        }
+    }
+}
+
+fn configure(canvas: &mut TerminalCanvas, options: &Options) {
+    // See https://no-color.org
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    canvas.set_colors(options.color && !no_color);
+    if !options.hyperlinks {
+        canvas.set_hyperlinks(false);
+    }
+    canvas.set_styles(!options.plain);
+}
+
+fn run_pager(
+    canvas: TerminalCanvas,
+    events: &TerminalEvents,
+    keybindings: &Keybindings<Action>,
+    markdown: &Markdown,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut renderer = Renderer::new(
+        canvas,
+        Theme::default(),
+        LayoutOptions::default(),
+        options.width,
+    )?;
+    renderer.load_markdown(markdown);
+    pager::run(&mut renderer, events, keybindings)?;
+    Ok(())
+}
+
+// Output that doesn't go to a terminal has no size, so it's taken from the options or
+// from the environment
+fn output_width(options: &Options) -> usize {
+    const DEFAULT_WIDTH: usize = 80;
+    let columns = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok());
+    options
+        .width
+        .or(columns)
+        .filter(|&width| width > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+fn show(options: &Options) -> Result<(), Box<dyn Error>> {
+    let texts = options
+        .inputs
+        .iter()
+        .map(read_input)
+        .collect::<Result<Vec<String>, _>>()?;
+    let mut markdown = Markdown {
+        elements: Vec::new(),
+    };
+    for text in texts.iter() {
+        let parsed = Markdown::parse(text).map_err(|()| "the document can't be parsed")?;
+        markdown.elements.extend(parsed.elements);
+    }
+    if !io::stdout().is_terminal() {
+        let (mut canvas, _) = terminal::start()?;
+        configure(&mut canvas, options);
+        let width = output_width(options);
+        renderer::render(
+            &mut canvas,
+            &markdown,
+            &Theme::default(),
+            &LayoutOptions::default(),
+            width,
+        )?;
+        return Ok(());
+    }
 
-    println!("{}", terminal_canvas.width().unwrap());
-    renderer::render(&mut terminal_canvas, &markdown);
+    let keybindings = pager::default_keybindings()?;
+    let (mut canvas, events) = terminal::start_in_raw_mode()?;
+    configure(&mut canvas, options);
+    let result = run_pager(canvas, &events, &keybindings, &markdown, options);
+    terminal::exit()?;
+    result
+}
+
+fn main() {
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Show(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("md {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => usage_error(e),
+    };
+    if options.inputs.contains(&Input::Stdin) && io::stdin().is_terminal() {
+        usage_error("no files given, and standard input is a terminal");
+    }
 
-    terminal::exit();
+    if let Err(e) = show(&options) {
+        eprintln!("md: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,1020 @@
+use std::{collections::HashMap, ops::Range};
+
+use super::{
+    link::{normalize_label, parse_inline_link_tail, parse_link_label, References},
+    Link, Separator, Style, StyledWord,
+};
+
+enum NodeKind {
+    Text,
+    Code,
+    DelimiterRun { remaining: usize },
+    LineEnding { hard: bool },
+    // Shows the text it's replaced with instead of its source
+    Entity(&'static str),
+}
+
+struct Node<'a> {
+    kind: NodeKind,
+    line: usize,
+    range: Range<usize>,
+    style: Style,
+    link: Option<Link<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn new(kind: NodeKind, line: usize, range: Range<usize>) -> Self {
+        Self {
+            kind,
+            line,
+            range,
+            style: Default::default(),
+            link: None,
+        }
+    }
+
+    /// The part of the source line that ends up on screen.
+    fn visible_range(&self) -> Range<usize> {
+        match self.kind {
+            NodeKind::Text | NodeKind::Code | NodeKind::Entity(_) => self.range.clone(),
+            NodeKind::DelimiterRun { remaining } => self.range.start..self.range.start + remaining,
+            NodeKind::LineEnding { .. } => self.range.start..self.range.start,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        match self.kind {
+            NodeKind::DelimiterRun { remaining } => remaining,
+            _ => 0,
+        }
+    }
+
+    fn consume(&mut self, n: usize) {
+        if let NodeKind::DelimiterRun { ref mut remaining } = self.kind {
+            *remaining -= n;
+        }
+    }
+}
+
+struct Delimiter {
+    node: usize,
+    character: char,
+    length: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+// An opening `[` that might start a link
+struct Bracket {
+    node: usize,
+    line: usize,
+    // Where the link text starts
+    start: usize,
+    // Delimiters above this index are inside of the link text
+    delimiters: usize,
+    // Links can't contain other links, so brackets before a link are deactivated
+    active: bool,
+}
+
+// The entities for characters that affect where lines break, and the ones that would
+// otherwise be taken for markup. Other entities are left as they are, because the words
+// can only hold text from the source or from here.
+const ENTITIES: [(&str, &str); 7] = [
+    ("nbsp", "\u{a0}"),
+    ("shy", "\u{ad}"),
+    ("ZeroWidthSpace", "\u{200b}"),
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+];
+
+/// Parses an entity or a numeric character reference, like `&nbsp;` or `&#160;`, at the
+/// start of `s`. Returns what it stands for and the length of the reference.
+fn parse_entity(s: &str) -> Option<(&'static str, usize)> {
+    let end = s.find(';')?;
+    let name = &s[1..end];
+    let replacement = if let Some(number) = name.strip_prefix('#') {
+        let code_point = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+            Some(hex)
+                if (1..=6).contains(&hex.len()) && hex.bytes().all(|b| b.is_ascii_hexdigit()) =>
+            {
+                u32::from_str_radix(hex, 16).ok()?
+            }
+            None if (1..=7).contains(&number.len())
+                && number.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                number.parse().ok()?
+            }
+            _ => return None,
+        };
+        let c = char::from_u32(code_point)?;
+        ENTITIES
+            .iter()
+            .map(|&(_, replacement)| replacement)
+            .find(|replacement| replacement.starts_with(c))?
+    } else {
+        ENTITIES
+            .iter()
+            .find(|&&(entity, _)| entity == name)
+            .map(|&(_, replacement)| replacement)?
+    };
+    Some((replacement, end + 1))
+}
+
+fn backtick_run_length(s: &str) -> usize {
+    s.bytes().take_while(|&b| b == b'`').count()
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || (!c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace())
+}
+
+// The beginning and the end of a line count as whitespace
+fn is_whitespace_or_edge(c: Option<char>) -> bool {
+    c.is_none_or(char::is_whitespace)
+}
+
+fn is_left_flanking(before: Option<char>, after: Option<char>) -> bool {
+    match after {
+        Some(after) if !after.is_whitespace() => {
+            !is_punctuation(after)
+                || is_whitespace_or_edge(before)
+                || before.is_some_and(is_punctuation)
+        }
+        _ => false,
+    }
+}
+
+fn is_right_flanking(before: Option<char>, after: Option<char>) -> bool {
+    is_left_flanking(after, before)
+}
+
+/// Parses an autolink like `<https://example.com>`, returning the URI and the number of
+/// bytes it takes up in `s`.
+fn parse_autolink(s: &str) -> Option<(&str, usize)> {
+    let rest = s.strip_prefix('<')?;
+    let end = rest
+        .find(|c: char| c == '>' || c == '<' || c.is_ascii_whitespace() || c.is_ascii_control())?;
+    if !rest[end..].starts_with('>') {
+        return None;
+    }
+    let uri = &rest[..end];
+    let (scheme, _) = uri.split_once(':')?;
+    let valid_scheme = (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'.' || b == b'-');
+    if valid_scheme {
+        Some((uri, end + 2))
+    } else {
+        None
+    }
+}
+
+struct InlineParser<'a, 'l> {
+    lines: &'l [&'a str],
+    references: &'l References<'a>,
+    nodes: Vec<Node<'a>>,
+    delimiters: Vec<Delimiter>,
+    brackets: Vec<Bracket>,
+    // Where the last run of backticks of each length is, which is complete once a search for
+    // a closing run has gone through to the end of the text
+    backtick_runs: HashMap<usize, (usize, usize)>,
+    backticks_scanned: bool,
+}
+
+impl<'a, 'l> InlineParser<'a, 'l> {
+    fn new(lines: &'l [&'a str], references: &'l References<'a>) -> Self {
+        Self {
+            lines,
+            references,
+            nodes: Vec::new(),
+            delimiters: Vec::new(),
+            brackets: Vec::new(),
+            backtick_runs: HashMap::new(),
+            backticks_scanned: false,
+        }
+    }
+
+    fn tokenize(&mut self) {
+        let mut line_index = 0;
+        let mut text_start = 0;
+        let mut i = 0;
+        while line_index < self.lines.len() {
+            let line = self.lines[line_index];
+            let c = match line[i..].chars().next() {
+                Some(c) => c,
+                None => {
+                    line_index += 1;
+                    let is_last_line = line_index == self.lines.len();
+                    // The backslash of an escaped backslash would already have been pushed
+                    let backslash = line[text_start..i].ends_with('\\');
+                    let hard = !is_last_line && (backslash || line.ends_with("  "));
+                    if hard && backslash {
+                        self.push_text(line_index - 1, text_start..i - 1);
+                    } else {
+                        self.push_text(line_index - 1, text_start..i);
+                    }
+                    if !is_last_line {
+                        self.nodes
+                            .push(Node::new(NodeKind::LineEnding { hard }, line_index, 0..0));
+                    }
+                    text_start = 0;
+                    i = 0;
+                    continue;
+                }
+            };
+
+            match c {
+                '\\' if line[i + 1..].starts_with(|c: char| c.is_ascii_punctuation()) => {
+                    self.push_text(line_index, text_start..i);
+                    self.push_text(line_index, i + 1..i + 2);
+                    i += 2;
+                    text_start = i;
+                }
+                '*' | '_' => {
+                    self.push_text(line_index, text_start..i);
+                    i = self.push_delimiter_run(line_index, i, c);
+                    text_start = i;
+                }
+                '`' => {
+                    let length = backtick_run_length(&line[i..]);
+                    match self.find_closing_backticks(line_index, i + length, length) {
+                        Some((end_line, end)) => {
+                            self.push_text(line_index, text_start..i);
+                            self.push_code_span((line_index, i + length), (end_line, end));
+                            line_index = end_line;
+                            i = end + length;
+                            text_start = i;
+                        }
+                        // Not a code span, so the backticks are just text
+                        None => i += length,
+                    }
+                }
+                '&' => match parse_entity(&line[i..]) {
+                    Some((text, length)) => {
+                        self.push_text(line_index, text_start..i);
+                        self.nodes.push(Node::new(
+                            NodeKind::Entity(text),
+                            line_index,
+                            i..i + length,
+                        ));
+                        i += length;
+                        text_start = i;
+                    }
+                    None => i += 1,
+                },
+                '<' => match parse_autolink(&line[i..]) {
+                    Some((uri, length)) => {
+                        self.push_text(line_index, text_start..i);
+                        let mut node = Node::new(NodeKind::Text, line_index, i + 1..i + length - 1);
+                        node.link = Some(Link {
+                            destination: uri,
+                            title: None,
+                        });
+                        self.nodes.push(node);
+                        i += length;
+                        text_start = i;
+                    }
+                    None => i += 1,
+                },
+                '[' => {
+                    self.push_text(line_index, text_start..i);
+                    self.brackets.push(Bracket {
+                        node: self.nodes.len(),
+                        line: line_index,
+                        start: i + 1,
+                        delimiters: self.delimiters.len(),
+                        active: true,
+                    });
+                    self.push_text(line_index, i..i + 1);
+                    i += 1;
+                    text_start = i;
+                }
+                ']' => {
+                    self.push_text(line_index, text_start..i);
+                    text_start = i;
+                    i += 1;
+                    if let Some(end) = self.close_bracket(line_index, text_start) {
+                        i = end;
+                        text_start = end;
+                    }
+                }
+                _ => i += c.len_utf8(),
+            }
+        }
+    }
+
+    /// Tries to turn the text between the last `[` and the `]` at `end` into a link. Returns
+    /// where the link ends if that works out.
+    fn close_bracket(&mut self, line_index: usize, end: usize) -> Option<usize> {
+        let bracket = self.brackets.pop()?;
+        if !bracket.active {
+            return None;
+        }
+
+        let line = self.lines[line_index];
+        let rest = &line[end + 1..];
+        let (link, length) = if let Some((link, length)) = parse_inline_link_tail(rest) {
+            (link, length)
+        } else if let Some((label, length)) = parse_link_label(rest) {
+            (*self.references.get(&normalize_label(label))?, length)
+        } else {
+            // Collapsed and shortcut references use the link text as the label
+            let length = if rest.starts_with("[]") { 2 } else { 0 };
+            let label = self.text_between((bracket.line, bracket.start), (line_index, end));
+            (*self.references.get(&normalize_label(&label))?, length)
+        };
+
+        self.process_emphasis(bracket.delimiters);
+        self.delimiters.truncate(bracket.delimiters);
+        self.nodes[bracket.node].range.end = self.nodes[bracket.node].range.start;
+        for node in &mut self.nodes[bracket.node + 1..] {
+            node.link = Some(link);
+        }
+        for bracket in &mut self.brackets {
+            bracket.active = false;
+        }
+        Some(end + 1 + length)
+    }
+
+    fn text_between(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        if start.0 == end.0 {
+            return self.lines[start.0][start.1..end.1].to_string();
+        }
+        let mut text = self.lines[start.0][start.1..].to_string();
+        for line in &self.lines[start.0 + 1..end.0] {
+            text.push('\n');
+            text.push_str(line);
+        }
+        text.push('\n');
+        text.push_str(&self.lines[end.0][..end.1]);
+        text
+    }
+
+    fn find_closing_backticks(
+        &mut self,
+        mut line_index: usize,
+        mut from: usize,
+        length: usize,
+    ) -> Option<(usize, usize)> {
+        // Without this, every unclosed run would scan the rest of the text again
+        if self.backticks_scanned
+            && self
+                .backtick_runs
+                .get(&length)
+                .is_none_or(|&last_run| last_run < (line_index, from))
+        {
+            return None;
+        }
+        while line_index < self.lines.len() {
+            let line = self.lines[line_index];
+            while let Some(offset) = line[from..].find('`') {
+                let start = from + offset;
+                let run_length = backtick_run_length(&line[start..]);
+                self.backtick_runs.insert(run_length, (line_index, start));
+                if run_length == length {
+                    return Some((line_index, start));
+                }
+                from = start + run_length;
+            }
+            line_index += 1;
+            from = 0;
+        }
+        self.backticks_scanned = true;
+        None
+    }
+
+    fn push_code_span(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let mut pieces: Vec<(usize, Range<usize>)> = (start.0..=end.0)
+            .map(|line_index| {
+                let piece_start = if line_index == start.0 { start.1 } else { 0 };
+                let piece_end = if line_index == end.0 {
+                    end.1
+                } else {
+                    self.lines[line_index].len()
+                };
+                (line_index, piece_start..piece_end)
+            })
+            .collect();
+
+        // One space is stripped from both sides, so that backticks can be put
+        // inside of code spans, as in `` `foo` ``
+        let text = |&(line_index, ref range): &(usize, Range<usize>)| {
+            &self.lines[line_index][range.clone()]
+        };
+        let starts_with_space = text(&pieces[0]).starts_with(' ');
+        let ends_with_space = text(pieces.last().unwrap()).ends_with(' ');
+        let only_spaces = pieces
+            .iter()
+            .all(|piece| text(piece).bytes().all(|b| b == b' '));
+        if starts_with_space && ends_with_space && !only_spaces {
+            pieces[0].1.start += 1;
+            pieces.last_mut().unwrap().1.end -= 1;
+        }
+
+        for (i, (line_index, range)) in pieces.into_iter().enumerate() {
+            if i != 0 {
+                self.nodes.push(Node::new(
+                    NodeKind::LineEnding { hard: false },
+                    line_index,
+                    0..0,
+                ));
+            }
+            if !range.is_empty() {
+                let mut node = Node::new(NodeKind::Code, line_index, range);
+                node.style.code = true;
+                self.nodes.push(node);
+            }
+        }
+    }
+
+    fn push_text(&mut self, line: usize, range: Range<usize>) {
+        if !range.is_empty() {
+            self.nodes.push(Node::new(NodeKind::Text, line, range));
+        }
+    }
+
+    fn push_delimiter_run(&mut self, line_index: usize, start: usize, character: char) -> usize {
+        let line = self.lines[line_index];
+        let length = line[start..]
+            .bytes()
+            .take_while(|&b| b == character as u8)
+            .count();
+        let end = start + length;
+        let before = line[..start].chars().next_back();
+        let after = line[end..].chars().next();
+        let left_flanking = is_left_flanking(before, after);
+        let right_flanking = is_right_flanking(before, after);
+        let (can_open, can_close) = if character == '*' {
+            (left_flanking, right_flanking)
+        } else {
+            // Underscores can't be used for intraword emphasis
+            (
+                left_flanking && (!right_flanking || before.is_some_and(is_punctuation)),
+                right_flanking && (!left_flanking || after.is_some_and(is_punctuation)),
+            )
+        };
+
+        self.delimiters.push(Delimiter {
+            node: self.nodes.len(),
+            character,
+            length,
+            can_open,
+            can_close,
+        });
+        self.nodes.push(Node::new(
+            NodeKind::DelimiterRun { remaining: length },
+            line_index,
+            start..end,
+        ));
+        end
+    }
+
+    // The "multiple of 3" rule from the CommonMark spec
+    fn is_odd_match(&self, opener: &Delimiter, closer: &Delimiter) -> bool {
+        (opener.can_close || closer.can_open)
+            && (opener.length + closer.length).is_multiple_of(3)
+            && !(opener.length.is_multiple_of(3) && closer.length.is_multiple_of(3))
+    }
+
+    /// Matches up the delimiters above `stack_bottom`.
+    fn process_emphasis(&mut self, stack_bottom: usize) {
+        // Lowest node that can still be an opener, per kind of closer. This keeps the
+        // algorithm from searching the same unmatched openers over and over again.
+        let mut openers_bottom = HashMap::new();
+        let mut closer_index = stack_bottom;
+        while closer_index < self.delimiters.len() {
+            let closer = &self.delimiters[closer_index];
+            if !closer.can_close {
+                closer_index += 1;
+                continue;
+            }
+
+            let key = (closer.character, closer.can_open, closer.length % 3);
+            let bottom = openers_bottom.get(&key).copied().unwrap_or(0);
+            let opener_index = (stack_bottom..closer_index).rev().find(|&i| {
+                let opener = &self.delimiters[i];
+                opener.node >= bottom
+                    && opener.character == closer.character
+                    && opener.can_open
+                    && !self.is_odd_match(opener, closer)
+            });
+
+            match opener_index {
+                Some(opener_index) => {
+                    let opener_node = self.delimiters[opener_index].node;
+                    let closer_node = self.delimiters[closer_index].node;
+                    let strong = self.nodes[opener_node].remaining() >= 2
+                        && self.nodes[closer_node].remaining() >= 2;
+                    for node in &mut self.nodes[opener_node + 1..closer_node] {
+                        if strong {
+                            node.style.bold = true;
+                        } else {
+                            node.style.italic = true;
+                        }
+                    }
+                    let used = if strong { 2 } else { 1 };
+                    self.nodes[opener_node].consume(used);
+                    self.nodes[closer_node].consume(used);
+
+                    self.delimiters.drain(opener_index + 1..closer_index);
+                    closer_index = opener_index + 1;
+                    if self.nodes[opener_node].remaining() == 0 {
+                        self.delimiters.remove(opener_index);
+                        closer_index -= 1;
+                    }
+                    if self.nodes[closer_node].remaining() == 0 {
+                        self.delimiters.remove(closer_index);
+                    }
+                }
+                None => {
+                    openers_bottom.insert(key, closer.node);
+                    if closer.can_open {
+                        closer_index += 1;
+                    } else {
+                        self.delimiters.remove(closer_index);
+                    }
+                }
+            }
+        }
+    }
+
+    fn into_words(self) -> Vec<StyledWord<'a>> {
+        let mut words: Vec<StyledWord<'a>> = Vec::new();
+        // Where the last word ended, if the next piece of text can continue it
+        let mut word_end: Option<(usize, usize)> = None;
+        for node in self.nodes.iter() {
+            let line = self.lines[node.line];
+            let range = node.visible_range();
+            if range.is_empty() {
+                if let NodeKind::LineEnding { hard } = node.kind {
+                    word_end = None;
+                    if let (true, Some(word)) = (hard, words.last_mut()) {
+                        word.separator = Separator::LineBreak;
+                    }
+                }
+                continue;
+            }
+
+            // Code spans keep their spaces, so they are never split into multiple words
+            if let NodeKind::Code | NodeKind::Entity(_) = node.kind {
+                push_word(&mut words, &mut word_end, line, node, range);
+                continue;
+            }
+
+            let mut start = range.start;
+            for (i, c) in line[range.clone()].char_indices() {
+                let i = range.start + i;
+                if c.is_ascii_whitespace() {
+                    if start < i {
+                        push_word(&mut words, &mut word_end, line, node, start..i);
+                    }
+                    word_end = None;
+                    start = i + c.len_utf8();
+                }
+            }
+            if start < range.end {
+                push_word(&mut words, &mut word_end, line, node, start..range.end);
+            }
+        }
+        words
+    }
+}
+
+// Text that isn't separated from the previous word by whitespace continues it. If the
+// text also directly follows the previous word in the source and looks the same, the two
+// are merged, otherwise the new text becomes another fragment of the same word.
+fn push_word<'a>(
+    words: &mut Vec<StyledWord<'a>>,
+    word_end: &mut Option<(usize, usize)>,
+    line: &'a str,
+    node: &Node<'a>,
+    range: Range<usize>,
+) {
+    let previous = match *word_end {
+        Some(end) => words.last_mut().map(|previous| (previous, end)),
+        None => None,
+    };
+    let text = match node.kind {
+        NodeKind::Entity(text) => text,
+        _ => &line[range.clone()],
+    };
+    if let Some((previous, end)) = previous {
+        // The text of entities isn't in the source, so it's never merged with anything
+        if end == (node.line, range.start)
+            && !matches!(node.kind, NodeKind::Entity(_))
+            && line[..range.start].ends_with(previous.text)
+            && previous.style == node.style
+            && previous.link == node.link
+        {
+            let start = range.start - previous.text.len();
+            previous.text = &line[start..range.end];
+            *word_end = Some((node.line, range.end));
+            return;
+        }
+        previous.separator = Separator::Nothing;
+    }
+    words.push(StyledWord {
+        text,
+        style: node.style,
+        link: node.link,
+        separator: Separator::Space,
+    });
+    *word_end = Some((node.line, range.end));
+}
+
+pub fn parse_inlines<'a>(lines: &[&'a str], references: &References<'a>) -> Vec<StyledWord<'a>> {
+    // Leading whitespace is never a part of the content
+    let lines: Vec<&str> = lines.iter().map(|line| line.trim_start()).collect();
+    let mut parser = InlineParser::new(&lines, references);
+    parser.tokenize();
+    parser.process_emphasis(0);
+    parser.into_words()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_code(text: &str) -> Vec<(&str, bool)> {
+        let lines: Vec<&str> = text.lines().collect();
+        parse_inlines(&lines, &References::new())
+            .into_iter()
+            .map(|word| (word.text, word.style.code))
+            .collect()
+    }
+
+    fn parse(text: &str) -> Vec<(&str, bool, bool)> {
+        let lines: Vec<&str> = text.lines().collect();
+        parse_inlines(&lines, &References::new())
+            .into_iter()
+            .map(|word| (word.text, word.style.bold, word.style.italic))
+            .collect()
+    }
+
+    fn parse_links<'a>(
+        text: &'a str,
+        references: &References<'a>,
+    ) -> Vec<(&'a str, Option<&'a str>)> {
+        let lines: Vec<&str> = text.lines().collect();
+        parse_inlines(&lines, references)
+            .into_iter()
+            .map(|word| (word.text, word.link.map(|link| link.destination)))
+            .collect()
+    }
+
+    fn references() -> References<'static> {
+        let mut references = References::new();
+        references.insert(
+            "foo bar".to_string(),
+            Link {
+                destination: "/ref",
+                title: Some("title"),
+            },
+        );
+        references
+    }
+
+    #[test]
+    fn plain_text_is_split_into_words() {
+        assert_eq!(
+            parse("  some plain\n text  "),
+            vec![
+                ("some", false, false),
+                ("plain", false, false),
+                ("text", false, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn simple_emphasis() {
+        for text in &["*em*", "_em_"] {
+            assert_eq!(parse(text), vec![("em", false, true)]);
+        }
+        for text in &["**strong**", "__strong__"] {
+            assert_eq!(parse(text), vec![("strong", true, false)]);
+        }
+        for text in &["***both***", "___both___", "*__both__*", "**_both_**"] {
+            assert_eq!(parse(text), vec![("both", true, true)]);
+        }
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            parse("*a **b** c*"),
+            vec![("a", false, true), ("b", true, true), ("c", false, true)]
+        );
+        assert_eq!(
+            parse("**a *b* c**"),
+            vec![("a", true, false), ("b", true, true), ("c", true, false)]
+        );
+    }
+
+    #[test]
+    fn emphasis_spans_lines() {
+        assert_eq!(
+            parse("*one\ntwo*"),
+            vec![("one", false, true), ("two", false, true)]
+        );
+    }
+
+    #[test]
+    fn intraword_emphasis() {
+        assert_eq!(
+            parse("foo*bar*baz"),
+            vec![
+                ("foo", false, false),
+                ("bar", false, true),
+                ("baz", false, false)
+            ]
+        );
+        assert_eq!(
+            parse("un**frigging**believable"),
+            vec![
+                ("un", false, false),
+                ("frigging", true, false),
+                ("believable", false, false)
+            ]
+        );
+        assert_eq!(
+            parse("snake_case_name"),
+            vec![("snake_case_name", false, false)]
+        );
+        assert_eq!(parse("foo_bar_"), vec![("foo_bar_", false, false)]);
+    }
+
+    #[test]
+    fn not_emphasis() {
+        for text in &[
+            "* not em *",
+            "a * b",
+            "**",
+            "2_3_4 is not",
+            "_ not _",
+            "\\*escaped\\*",
+        ] {
+            assert!(
+                parse(text)
+                    .iter()
+                    .all(|&(_, bold, italic)| !bold && !italic),
+                "{:?} shouldn't contain emphasis",
+                text
+            );
+        }
+        assert_eq!(parse("\\*escaped"), vec![("*escaped", false, false)]);
+    }
+
+    #[test]
+    fn unbalanced_delimiters() {
+        assert_eq!(
+            parse("**foo*"),
+            vec![("*", false, false), ("foo", false, true)]
+        );
+        assert_eq!(
+            parse("*foo**"),
+            vec![("foo", false, true), ("*", false, false)]
+        );
+        assert_eq!(parse("**foo"), vec![("**foo", false, false)]);
+    }
+
+    #[test]
+    fn entities() {
+        let words = |text| -> Vec<(&str, Separator)> {
+            parse_inlines(&[text], &References::new())
+                .into_iter()
+                .map(|word| (word.text, word.separator))
+                .collect()
+        };
+        assert_eq!(
+            words("10&nbsp;kg &#xAD;&amp;lt;"),
+            vec![
+                ("10", Separator::Nothing),
+                ("\u{a0}", Separator::Nothing),
+                ("kg", Separator::Space),
+                ("\u{ad}", Separator::Nothing),
+                ("&", Separator::Nothing),
+                ("lt;", Separator::Space),
+            ]
+        );
+        assert_eq!(
+            words("&copy; &#xZZ; &#8203;"),
+            vec![
+                ("&copy;", Separator::Space),
+                ("&#xZZ;", Separator::Space),
+                ("\u{200b}", Separator::Space),
+            ]
+        );
+    }
+
+    #[test]
+    fn styled_fragments_stay_in_one_word() {
+        let lines = ["**bold**word `code`, a\\*b end"];
+        let words: Vec<(&str, Separator)> = parse_inlines(&lines, &References::new())
+            .into_iter()
+            .map(|word| (word.text, word.separator))
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                ("bold", Separator::Nothing),
+                ("word", Separator::Space),
+                ("code", Separator::Nothing),
+                (",", Separator::Space),
+                ("a", Separator::Nothing),
+                ("*b", Separator::Space),
+                ("end", Separator::Space),
+            ]
+        );
+    }
+
+    #[test]
+    fn punctuation_flanking() {
+        assert_eq!(parse("*\"quoted\"*"), vec![("\"quoted\"", false, true)]);
+        assert_eq!(parse("a*\"b\"*c"), vec![("a*\"b\"*c", false, false)]);
+    }
+
+    #[test]
+    fn rule_of_three() {
+        assert_eq!(
+            parse("*foo**bar**baz*"),
+            vec![
+                ("foo", false, true),
+                ("bar", true, true),
+                ("baz", false, true)
+            ]
+        );
+        assert_eq!(parse("*foo**bar*"), vec![("foo**bar", false, true)]);
+    }
+
+    #[test]
+    fn code_spans() {
+        assert_eq!(
+            parse_code("a `b  c` d"),
+            vec![("a", false), ("b  c", true), ("d", false)]
+        );
+        assert_eq!(parse_code("``foo ` bar``"), vec![("foo ` bar", true)]);
+        assert_eq!(parse_code("` `` `"), vec![("``", true)]);
+        assert_eq!(parse_code("`  ``  `"), vec![(" `` ", true)]);
+        assert_eq!(parse_code("`   `"), vec![("   ", true)]);
+        assert_eq!(parse_code("` a`"), vec![(" a", true)]);
+    }
+
+    #[test]
+    fn code_spans_span_lines() {
+        assert_eq!(
+            parse_code("`foo\nbar  baz`"),
+            vec![("foo", true), ("bar  baz", true)]
+        );
+    }
+
+    #[test]
+    fn code_spans_take_precedence() {
+        assert_eq!(
+            parse_code("*a `*` b*"),
+            vec![("a", false), ("*", true), ("b", false)]
+        );
+        assert_eq!(parse("*a `*` b*")[0], ("a", false, true));
+        assert_eq!(parse_code("`\\*`"), vec![("\\*", true)]);
+    }
+
+    #[test]
+    fn unmatched_backticks_are_text() {
+        assert_eq!(parse_code("``foo`"), vec![("``foo`", false)]);
+        assert_eq!(
+            parse_code("`foo``bar``"),
+            vec![("`foo", false), ("bar", true)]
+        );
+    }
+
+    #[test]
+    fn many_unmatched_backticks_are_text() {
+        // Each run used to scan the rest of the text for a closing run again
+        let runs: Vec<String> = (2..=1000).map(|length| "`".repeat(length)).collect();
+        let text = format!("{}\n` c `", runs.join(" "));
+
+        let words = parse_code(&text);
+        assert_eq!(words.len(), 1000);
+        assert!(words[..999].iter().all(|&(_, code)| !code));
+        assert_eq!(words[999], ("c", true));
+    }
+
+    #[test]
+    fn inline_links() {
+        let references = References::new();
+        assert_eq!(
+            parse_links("see [the *docs*](/url \"title\").", &references),
+            vec![
+                ("see", None),
+                ("the", Some("/url")),
+                ("docs", Some("/url")),
+                (".", None)
+            ]
+        );
+        assert_eq!(
+            parse_links("[a](<b c>)", &references),
+            vec![("a", Some("b c"))]
+        );
+        assert_eq!(
+            parse_links("[a] (/url)", &references),
+            vec![("[a]", None), ("(/url)", None)]
+        );
+    }
+
+    #[test]
+    fn emphasis_stays_inside_links() {
+        let lines = ["*[a*](/url)"];
+        let words = parse_inlines(&lines, &References::new());
+        assert_eq!(words[0].text, "*");
+        assert!(!words[1].style.italic);
+        assert_eq!(words[1].link.unwrap().destination, "/url");
+    }
+
+    #[test]
+    fn reference_links() {
+        let references = references();
+        for text in &["[text][Foo  Bar]", "[Foo bar][]", "[foo\nbar]"] {
+            assert!(
+                parse_links(text, &references)
+                    .iter()
+                    .all(|&(_, destination)| destination == Some("/ref")),
+                "{:?} should be a link",
+                text
+            );
+        }
+        assert_eq!(
+            parse_links("[text][nope]", &references)[0],
+            ("[text][nope]", None)
+        );
+        assert_eq!(parse_links("[nope]", &references), vec![("[nope]", None)]);
+    }
+
+    #[test]
+    fn links_cannot_contain_links() {
+        assert_eq!(
+            parse_links("[a [b](/inner)](/outer)", &References::new()),
+            vec![("[a", None), ("b", Some("/inner")), ("](/outer)", None)]
+        );
+    }
+
+    #[test]
+    fn autolinks() {
+        let references = References::new();
+        assert_eq!(
+            parse_links("go to <https://example.com/a_b*c*>!", &references),
+            vec![
+                ("go", None),
+                ("to", None),
+                (
+                    "https://example.com/a_b*c*",
+                    Some("https://example.com/a_b*c*")
+                ),
+                ("!", None)
+            ]
+        );
+        assert_eq!(parse_links("<not a link>", &references)[0], ("<not", None));
+        assert_eq!(parse_links("<x:y>", &references), vec![("<x:y>", None)]);
+    }
+
+    fn parse_breaks(text: &str) -> Vec<(&str, bool)> {
+        let lines: Vec<&str> = text.lines().collect();
+        parse_inlines(&lines, &References::new())
+            .into_iter()
+            .map(|word| (word.text, word.separator == Separator::LineBreak))
+            .collect()
+    }
+
+    #[test]
+    fn hard_line_breaks() {
+        assert_eq!(
+            parse_breaks("one  \ntwo three\\\nfour"),
+            vec![
+                ("one", true),
+                ("two", false),
+                ("three", true),
+                ("four", false)
+            ]
+        );
+        assert_eq!(
+            parse_breaks("*one*   \ntwo"),
+            vec![("one", true), ("two", false)]
+        );
+    }
+
+    #[test]
+    fn not_hard_line_breaks() {
+        assert!(parse_breaks("one \ntwo\\\\\nthree\\")
+            .iter()
+            .all(|&(_, hard_break)| !hard_break));
+        assert_eq!(parse_breaks("three\\"), vec![("three\\", false)]);
+        assert_eq!(parse_breaks("one  "), vec![("one", false)]);
+        assert_eq!(
+            parse_breaks("`one  \ntwo`"),
+            vec![("one  ", false), ("two", false)]
+        );
+    }
+}
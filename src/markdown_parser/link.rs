@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use super::Link;
+
+/// Link reference definitions, keyed by their normalized labels.
+pub type References<'a> = HashMap<String, Link<'a>>;
+
+/// Labels are matched case-insensitively, with consecutive whitespace treated as one space.
+pub fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn skip_whitespace(s: &str) -> &str {
+    s.trim_start_matches([' ', '\t'])
+}
+
+/// Returns the destination and the number of bytes it takes up in `s`.
+pub fn parse_link_destination(s: &str) -> Option<(&str, usize)> {
+    if let Some(rest) = s.strip_prefix('<') {
+        let mut escaped = false;
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '<' => return None,
+                '>' => return Some((&rest[..i], i + 2)),
+                _ => {}
+            }
+        }
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut end = s.len();
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                end = i;
+                break;
+            }
+            ')' => depth -= 1,
+            _ if c.is_ascii_whitespace() || c.is_ascii_control() => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    if end == 0 || depth != 0 {
+        None
+    } else {
+        Some((&s[..end], end))
+    }
+}
+
+/// Parses a `"title"`, `'title'` or `(title)`, returning the title without the quotes and
+/// the number of bytes it takes up in `s`.
+pub fn parse_link_title(s: &str) -> Option<(&str, usize)> {
+    let closing = match s.chars().next()? {
+        '"' => '"',
+        '\'' => '\'',
+        '(' => ')',
+        _ => return None,
+    };
+    let mut escaped = false;
+    for (i, c) in s[1..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' if closing == ')' => return None,
+            _ if c == closing => return Some((&s[1..1 + i], i + 2)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses the `(destination "title")` part of an inline link. Returns the link and the
+/// number of bytes it takes up in `s`.
+pub fn parse_inline_link_tail(s: &str) -> Option<(Link<'_>, usize)> {
+    let rest = s.strip_prefix('(')?;
+    let rest = skip_whitespace(rest);
+    let (destination, rest) = match parse_link_destination(rest) {
+        Some((destination, length)) => (destination, &rest[length..]),
+        None => ("", rest),
+    };
+    let after_destination = skip_whitespace(rest);
+    // The title has to be separated from the destination
+    let (title, rest) = match parse_link_title(after_destination) {
+        Some((title, length)) if after_destination.len() < rest.len() || destination.is_empty() => {
+            (Some(title), skip_whitespace(&after_destination[length..]))
+        }
+        _ => (None, after_destination),
+    };
+    let rest = rest.strip_prefix(')')?;
+    Some((Link { destination, title }, s.len() - rest.len()))
+}
+
+/// Parses a `[label]` and returns the label without the brackets, as well as the number of
+/// bytes it takes up in `s`.
+pub fn parse_link_label(s: &str) -> Option<(&str, usize)> {
+    let rest = s.strip_prefix('[')?;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => return None,
+            ']' => {
+                let label = &rest[..i];
+                return if label.len() > 999 || label.trim().is_empty() {
+                    None
+                } else {
+                    Some((label, i + 2))
+                };
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses a `[label]: destination "title"` definition, where the title may also be on the
+/// following line. Returns the normalized label, the link and the number of lines used.
+pub fn parse_reference_definition<'a>(lines: &[&'a str]) -> Option<(String, Link<'a>, usize)> {
+    let line = lines.first()?.trim_start_matches(' ');
+    if lines[0].len() - line.len() > 3 {
+        return None;
+    }
+    let (label, length) = parse_link_label(line)?;
+    let rest = line[length..].strip_prefix(':')?;
+    let rest = skip_whitespace(rest);
+    let (destination, length) = parse_link_destination(rest)?;
+    let rest = &rest[length..];
+
+    let after_destination = skip_whitespace(rest);
+    if after_destination.is_empty() {
+        let title_on_next_line = lines.get(1).and_then(|next_line| {
+            let next_line = skip_whitespace(next_line);
+            let (title, length) = parse_link_title(next_line)?;
+            if next_line[length..].trim().is_empty() {
+                Some(title)
+            } else {
+                None
+            }
+        });
+        let line_count = if title_on_next_line.is_some() { 2 } else { 1 };
+        let link = Link {
+            destination,
+            title: title_on_next_line,
+        };
+        return Some((normalize_label(label), link, line_count));
+    }
+
+    // Anything other than a title after the destination makes this an ordinary paragraph
+    if after_destination.len() == rest.len() {
+        return None;
+    }
+    let (title, length) = parse_link_title(after_destination)?;
+    if !after_destination[length..].trim().is_empty() {
+        return None;
+    }
+    let link = Link {
+        destination,
+        title: Some(title),
+    };
+    Some((normalize_label(label), link, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link<'a>(destination: &'a str, title: Option<&'a str>) -> Link<'a> {
+        Link { destination, title }
+    }
+
+    #[test]
+    fn labels_are_normalized() {
+        assert_eq!(normalize_label("  Foo \n  BAR "), "foo bar");
+    }
+
+    #[test]
+    fn destinations() {
+        assert_eq!(parse_link_destination("/url rest"), Some(("/url", 4)));
+        assert_eq!(parse_link_destination("<my url>)"), Some(("my url", 8)));
+        assert_eq!(parse_link_destination("a(b)c)"), Some(("a(b)c", 5)));
+        assert_eq!(parse_link_destination("a\\)b)"), Some(("a\\)b", 4)));
+        assert_eq!(parse_link_destination("a(b"), None);
+        assert_eq!(parse_link_destination("<a<b>"), None);
+        assert_eq!(parse_link_destination(""), None);
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(parse_link_title("\"a title\" rest"), Some(("a title", 9)));
+        assert_eq!(parse_link_title("'a'"), Some(("a", 3)));
+        assert_eq!(parse_link_title("(a \\) b)"), Some(("a \\) b", 8)));
+        assert_eq!(parse_link_title("\"unclosed"), None);
+    }
+
+    #[test]
+    fn inline_link_tails() {
+        assert_eq!(
+            parse_inline_link_tail("(/url) rest"),
+            Some((link("/url", None), 6))
+        );
+        assert_eq!(
+            parse_inline_link_tail("( /url  \"title\" )"),
+            Some((link("/url", Some("title")), 17))
+        );
+        assert_eq!(parse_inline_link_tail("()"), Some((link("", None), 2)));
+        assert_eq!(
+            parse_inline_link_tail("(/url\"title\")"),
+            Some((link("/url\"title\"", None), 13))
+        );
+        assert_eq!(parse_inline_link_tail("(/url"), None);
+        assert_eq!(parse_inline_link_tail("[label]"), None);
+    }
+
+    #[test]
+    fn reference_definitions() {
+        assert_eq!(
+            parse_reference_definition(&["[Foo]: /url"]),
+            Some(("foo".to_string(), link("/url", None), 1))
+        );
+        assert_eq!(
+            parse_reference_definition(&["   [foo]:  <a b>  'title'  "]),
+            Some(("foo".to_string(), link("a b", Some("title")), 1))
+        );
+        assert_eq!(
+            parse_reference_definition(&["[foo]: /url", "  \"title\"", "text"]),
+            Some(("foo".to_string(), link("/url", Some("title")), 2))
+        );
+        assert_eq!(
+            parse_reference_definition(&["[foo]: /url", "not a title"]),
+            Some(("foo".to_string(), link("/url", None), 1))
+        );
+    }
+
+    #[test]
+    fn not_reference_definitions() {
+        for lines in &[
+            vec!["[foo] /url"],
+            vec!["[foo]:"],
+            vec!["[foo]: /url \"title\" trailing"],
+            vec!["[foo]: /url trailing"],
+            vec!["    [foo]: /url"],
+            vec!["[]: /url"],
+        ] {
+            assert_eq!(parse_reference_definition(lines), None, "{:?}", lines);
+        }
+    }
+}
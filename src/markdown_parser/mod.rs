@@ -0,0 +1,1158 @@
+use inline::parse_inlines;
+use link::{parse_reference_definition, References};
+use table::{parse_delimiter_row, parse_row, parse_table_start};
+
+mod inline;
+mod link;
+mod table;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link<'a> {
+    pub destination: &'a str,
+    pub title: Option<&'a str>,
+}
+
+/// What comes between a word and the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    Space,
+    /// The next word continues this one in a different style, like in `**bold**word`.
+    Nothing,
+    /// A hard line break, from two spaces or a backslash at the end of a line.
+    LineBreak,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StyledWord<'a> {
+    pub text: &'a str,
+    pub style: Style,
+    pub link: Option<Link<'a>>,
+    pub separator: Separator,
+}
+
+impl<'a> From<&'a str> for StyledWord<'a> {
+    fn from(text: &'a str) -> Self {
+        StyledWord {
+            text,
+            style: Default::default(),
+            link: None,
+            separator: Separator::Space,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadingSize {
+    ExtraLarge,
+    Large,
+    Medium,
+    Small,
+    ExtraSmall,
+    Tiny,
+}
+
+impl HeadingSize {
+    fn from_level(level: usize) -> Option<Self> {
+        match level {
+            1 => Some(Self::ExtraLarge),
+            2 => Some(Self::Large),
+            3 => Some(Self::Medium),
+            4 => Some(Self::Small),
+            5 => Some(Self::ExtraSmall),
+            6 => Some(Self::Tiny),
+            _ => None,
+        }
+    }
+}
+
+pub struct Heading<'a> {
+    pub words: Vec<StyledWord<'a>>,
+    pub size: HeadingSize,
+}
+
+pub struct CodeBlock<'a> {
+    pub info: &'a str,
+    pub lines: Vec<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    Unordered,
+    Ordered { start: u64 },
+}
+
+pub struct List<'a> {
+    pub kind: ListKind,
+    pub items: Vec<Vec<MarkdownElement<'a>>>,
+    /// Loose lists have their items and the blocks inside them separated by blank lines.
+    pub loose: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+pub type TableCell<'a> = Vec<StyledWord<'a>>;
+
+pub struct Table<'a> {
+    pub alignments: Vec<ColumnAlignment>,
+    pub header: Vec<TableCell<'a>>,
+    pub rows: Vec<Vec<TableCell<'a>>>,
+}
+
+pub enum MarkdownElement<'a> {
+    Heading(Heading<'a>),
+    Paragraph(Vec<StyledWord<'a>>),
+    CodeBlock(CodeBlock<'a>),
+    List(List<'a>),
+    BlockQuote(Vec<MarkdownElement<'a>>),
+    Table(Table<'a>),
+}
+
+pub type Result<T> = std::result::Result<T, ()>;
+
+pub struct Markdown<'a> {
+    pub elements: Vec<MarkdownElement<'a>>,
+}
+
+impl<'a> Markdown<'a> {
+    pub fn parse(text: &'a str) -> Result<Self> {
+        let lines: Vec<&str> = text.lines().collect();
+        // Links can refer to definitions that come after them, so the definitions are
+        // collected with a separate pass over the document first
+        let no_references = References::new();
+        let mut first_pass = BlockParser::new(&lines, &no_references, 0);
+        first_pass.parse_blocks();
+        let references = first_pass.definitions;
+        Ok(Self {
+            elements: BlockParser::new(&lines, &references, 0).parse(),
+        })
+    }
+}
+
+const SPACE_OR_TAB: [char; 2] = [' ', '\t'];
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Strips up to three spaces of indentation, which is the most that block markers
+/// like `#` or `===` allow. Returns `None` if the line is indented further.
+fn strip_marker_indent(line: &str) -> Option<&str> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        None
+    } else {
+        Some(&line[indent..])
+    }
+}
+
+/// Strips `columns` columns of indentation, with tabs advancing to the next multiple of 4.
+/// Returns `None` if the line isn't indented that much.
+fn strip_indent(line: &str, columns: usize) -> Option<&str> {
+    let mut column = 0;
+    for (i, c) in line.char_indices() {
+        if column >= columns {
+            return Some(&line[i..]);
+        }
+        match c {
+            ' ' => column += 1,
+            '\t' => column += 4 - column % 4,
+            _ => return None,
+        }
+    }
+    if column >= columns {
+        Some("")
+    } else {
+        None
+    }
+}
+
+fn strip_up_to_spaces(line: &str, max: usize) -> &str {
+    let indent = line.bytes().take(max).take_while(|&b| b == b' ').count();
+    &line[indent..]
+}
+
+struct Fence<'a> {
+    character: u8,
+    length: usize,
+    indent: usize,
+    info: &'a str,
+}
+
+fn parse_opening_fence(line: &str) -> Option<Fence<'_>> {
+    let stripped = strip_marker_indent(line)?;
+    let character = *stripped.as_bytes().first()?;
+    if character != b'`' && character != b'~' {
+        return None;
+    }
+    let length = stripped.bytes().take_while(|&b| b == character).count();
+    let info = stripped[length..].trim();
+    if length < 3 || (character == b'`' && info.contains('`')) {
+        return None;
+    }
+    Some(Fence {
+        character,
+        length,
+        indent: line.len() - stripped.len(),
+        info,
+    })
+}
+
+fn is_closing_fence(line: &str, fence: &Fence) -> bool {
+    match strip_marker_indent(line) {
+        Some(stripped) => {
+            let length = stripped
+                .bytes()
+                .take_while(|&b| b == fence.character)
+                .count();
+            length >= fence.length && stripped[length..].trim().is_empty()
+        }
+        None => false,
+    }
+}
+
+fn parse_atx_heading(line: &str) -> Option<(HeadingSize, &str)> {
+    let line = strip_marker_indent(line)?;
+    let level = line.len() - line.trim_start_matches('#').len();
+    let size = HeadingSize::from_level(level)?;
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(SPACE_OR_TAB) {
+        return None;
+    }
+
+    let content = rest.trim_matches(SPACE_OR_TAB);
+    let without_closing = content.trim_end_matches('#');
+    let content = if without_closing.is_empty() {
+        without_closing
+    } else if without_closing.ends_with(SPACE_OR_TAB) {
+        without_closing.trim_end_matches(SPACE_OR_TAB)
+    } else {
+        // The hashes are part of the content, as in `# C#`
+        content
+    };
+    Some((size, content))
+}
+
+fn parse_setext_underline(line: &str) -> Option<HeadingSize> {
+    let line = strip_marker_indent(line)?.trim_end();
+    if !line.is_empty() && line.bytes().all(|b| b == b'=') {
+        Some(HeadingSize::ExtraLarge)
+    } else if !line.is_empty() && line.bytes().all(|b| b == b'-') {
+        Some(HeadingSize::Large)
+    } else {
+        None
+    }
+}
+
+struct ListMarker<'a> {
+    /// The bullet character, or the `.` or `)` after the number
+    delimiter: u8,
+    start: Option<u64>,
+    /// The column at which the content of the list item starts
+    content_indent: usize,
+    content: &'a str,
+}
+
+impl ListMarker<'_> {
+    fn continues_list(&self, other: &ListMarker) -> bool {
+        self.delimiter == other.delimiter && self.start.is_some() == other.start.is_some()
+    }
+
+    fn can_interrupt_paragraph(&self) -> bool {
+        !self.content.is_empty() && self.start.is_none_or(|start| start == 1)
+    }
+}
+
+fn parse_list_marker(line: &str) -> Option<ListMarker<'_>> {
+    let stripped = strip_marker_indent(line)?;
+    let indent = line.len() - stripped.len();
+    let digits = stripped.bytes().take_while(u8::is_ascii_digit).count();
+    let (start, marker_length) = match stripped.as_bytes().get(digits) {
+        Some(b'-') | Some(b'*') | Some(b'+') if digits == 0 => (None, 1),
+        Some(b'.') | Some(b')') if digits > 0 && digits <= 9 => {
+            (Some(stripped[..digits].parse().ok()?), digits + 1)
+        }
+        _ => return None,
+    };
+    let delimiter = stripped.as_bytes()[marker_length - 1];
+
+    let rest = &stripped[marker_length..];
+    let spaces = rest.len() - rest.trim_start_matches(SPACE_OR_TAB).len();
+    let (spaces, content) = if is_blank(rest) {
+        (1, "")
+    } else if spaces == 0 {
+        return None;
+    } else if spaces > 4 {
+        // The content is an indented code block
+        (1, &rest[1..])
+    } else {
+        (spaces, &rest[spaces..])
+    };
+    Some(ListMarker {
+        delimiter,
+        start,
+        content_indent: indent + marker_length + spaces,
+        content,
+    })
+}
+
+fn strip_block_quote_marker(line: &str) -> Option<&str> {
+    let rest = strip_marker_indent(line)?.strip_prefix('>')?;
+    Some(rest.strip_prefix(SPACE_OR_TAB).unwrap_or(rest))
+}
+
+fn interrupts_paragraph(line: &str) -> bool {
+    is_blank(line)
+        || parse_atx_heading(line).is_some()
+        || strip_block_quote_marker(line).is_some()
+        || parse_opening_fence(line).is_some()
+        || parse_list_marker(line).is_some_and(|marker| marker.can_interrupt_paragraph())
+}
+
+fn ends_in_paragraph(elements: &[MarkdownElement]) -> bool {
+    match elements.last() {
+        Some(MarkdownElement::Paragraph(_)) => true,
+        Some(MarkdownElement::BlockQuote(elements)) => ends_in_paragraph(elements),
+        Some(MarkdownElement::List(list)) => list
+            .items
+            .last()
+            .is_some_and(|item| ends_in_paragraph(item)),
+        _ => false,
+    }
+}
+
+/// The lines of a container block, like a block quote or a list item, as they're collected.
+/// A paragraph inside of the container can go on without the container's markers or
+/// indentation, so whether the lines end in a paragraph is tracked along the way.
+struct ContainerLines<'a> {
+    lines: Vec<&'a str>,
+    // How deep the blocks in the container are nested
+    depth: usize,
+    // Whether the lines end in a paragraph, or `None` if that's only known by parsing them
+    open_paragraph: Option<bool>,
+}
+
+impl<'a> ContainerLines<'a> {
+    fn new(lines: Vec<&'a str>, depth: usize) -> Self {
+        Self {
+            lines,
+            depth,
+            open_paragraph: None,
+        }
+    }
+
+    fn push(&mut self, line: &'a str) {
+        // Such a line goes on with an open paragraph, however deep the paragraph is nested.
+        // Anything else, like a delimiter row that might turn the paragraph's last line into
+        // a table header, needs the lines to be parsed again.
+        let continues_paragraph = strip_indent(line, 4).is_none()
+            && !interrupts_paragraph(line)
+            && parse_list_marker(line).is_none()
+            && parse_setext_underline(line).is_none()
+            && parse_delimiter_row(line).is_none();
+        self.open_paragraph = match self.open_paragraph {
+            Some(true) if continues_paragraph => Some(true),
+            _ => None,
+        };
+        self.lines.push(line);
+    }
+
+    fn is_lazy_continuation(&mut self, line: &str) -> bool {
+        if self
+            .lines
+            .last()
+            .is_none_or(|previous_line| is_blank(previous_line))
+            || interrupts_paragraph(line)
+            || parse_list_marker(line).is_some()
+            || parse_setext_underline(line).is_some()
+        {
+            return false;
+        }
+        let (lines, depth) = (&self.lines, self.depth);
+        *self.open_paragraph.get_or_insert_with(|| {
+            ends_in_paragraph(&BlockParser::new(lines, &References::new(), depth).parse())
+        })
+    }
+}
+
+/// Block quotes and lists can only be nested this deep. Each level goes through the lines of
+/// the ones inside of it again, so without a limit, a document made to be deeply nested
+/// would take very long to parse. Deeper markers are taken as text.
+const MAX_NESTING: usize = 32;
+
+struct BlockParser<'a, 'l> {
+    lines: &'l [&'a str],
+    references: &'l References<'a>,
+    definitions: References<'a>,
+    blank_line_between_blocks: bool,
+    // How many block quotes and list items the lines are inside of
+    depth: usize,
+}
+
+impl<'a, 'l> BlockParser<'a, 'l> {
+    fn new(lines: &'l [&'a str], references: &'l References<'a>, depth: usize) -> Self {
+        Self {
+            lines,
+            references,
+            definitions: References::new(),
+            blank_line_between_blocks: false,
+            depth,
+        }
+    }
+
+    fn parse(mut self) -> Vec<MarkdownElement<'a>> {
+        self.parse_blocks()
+    }
+
+    fn parse_blocks(&mut self) -> Vec<MarkdownElement<'a>> {
+        let mut elements = Vec::new();
+        loop {
+            if self.skip_blank_lines() && !elements.is_empty() && !self.lines.is_empty() {
+                self.blank_line_between_blocks = true;
+            }
+            let line = match self.lines.first() {
+                Some(line) => *line,
+                None => break,
+            };
+            if let Some(fence) = parse_opening_fence(line) {
+                self.lines = &self.lines[1..];
+                elements.push(self.parse_fenced_code_block(fence));
+            } else if let Some((size, content)) = parse_atx_heading(line) {
+                self.lines = &self.lines[1..];
+                elements.push(MarkdownElement::Heading(Heading {
+                    words: parse_inlines(&[content], self.references),
+                    size,
+                }));
+            } else if strip_indent(line, 4).is_some() {
+                elements.push(self.parse_indented_code_block());
+            } else if self.depth < MAX_NESTING && strip_block_quote_marker(line).is_some() {
+                elements.push(self.parse_block_quote());
+            } else if let Some(marker) =
+                parse_list_marker(line).filter(|_| self.depth < MAX_NESTING)
+            {
+                elements.push(self.parse_list(marker));
+            } else if let Some((label, link, line_count)) = parse_reference_definition(self.lines) {
+                self.lines = &self.lines[line_count..];
+                self.definitions.entry(label).or_insert(link);
+            } else if let Some(alignments) = self.parse_table_start_at(0) {
+                elements.push(self.parse_table(alignments));
+            } else {
+                elements.push(self.parse_paragraph());
+            }
+        }
+        elements
+    }
+
+    fn skip_blank_lines(&mut self) -> bool {
+        let blank_lines = self.lines.iter().take_while(|line| is_blank(line)).count();
+        self.lines = &self.lines[blank_lines..];
+        blank_lines > 0
+    }
+
+    fn parse_fenced_code_block(&mut self, fence: Fence<'a>) -> MarkdownElement<'a> {
+        // An unclosed code block extends to the end of the document
+        let end = self
+            .lines
+            .iter()
+            .position(|line| is_closing_fence(line, &fence))
+            .unwrap_or(self.lines.len());
+        let lines = self.lines[..end]
+            .iter()
+            .map(|line| strip_up_to_spaces(line, fence.indent))
+            .collect();
+        self.lines = &self.lines[(end + 1).min(self.lines.len())..];
+        MarkdownElement::CodeBlock(CodeBlock {
+            info: fence.info,
+            lines,
+        })
+    }
+
+    fn parse_indented_code_block(&mut self) -> MarkdownElement<'a> {
+        let mut end = self
+            .lines
+            .iter()
+            .position(|line| !is_blank(line) && strip_indent(line, 4).is_none())
+            .unwrap_or(self.lines.len());
+        while is_blank(self.lines[end - 1]) {
+            end -= 1;
+        }
+        let lines = self.lines[..end]
+            .iter()
+            .map(|line| strip_indent(line, 4).unwrap_or(""))
+            .collect();
+        self.lines = &self.lines[end..];
+        MarkdownElement::CodeBlock(CodeBlock { info: "", lines })
+    }
+
+    fn parse_block_quote(&mut self) -> MarkdownElement<'a> {
+        let mut lines = ContainerLines::new(Vec::new(), self.depth + 1);
+        let mut end = 0;
+        while end < self.lines.len() {
+            let line = self.lines[end];
+            if let Some(stripped) = strip_block_quote_marker(line) {
+                lines.push(stripped);
+            } else if lines.is_lazy_continuation(line) {
+                lines.push(line);
+            } else {
+                break;
+            }
+            end += 1;
+        }
+        self.lines = &self.lines[end..];
+        let mut parser = BlockParser::new(&lines.lines, self.references, self.depth + 1);
+        let elements = parser.parse_blocks();
+        self.add_definitions(parser.definitions);
+        MarkdownElement::BlockQuote(elements)
+    }
+
+    fn parse_list(&mut self, first_marker: ListMarker<'a>) -> MarkdownElement<'a> {
+        let kind = match first_marker.start {
+            Some(start) => ListKind::Ordered { start },
+            None => ListKind::Unordered,
+        };
+        let mut items = Vec::new();
+        let mut loose = false;
+        let mut marker = first_marker;
+        loop {
+            let (item, item_loose) = self.parse_list_item(&marker);
+            items.push(item);
+            loose |= item_loose;
+
+            let blank_lines = self.lines.iter().take_while(|line| is_blank(line)).count();
+            match self
+                .lines
+                .get(blank_lines)
+                .and_then(|line| parse_list_marker(line))
+            {
+                Some(next_marker) if next_marker.continues_list(&marker) => {
+                    loose |= blank_lines > 0;
+                    self.lines = &self.lines[blank_lines..];
+                    marker = next_marker;
+                }
+                _ => break,
+            }
+        }
+        MarkdownElement::List(List { kind, items, loose })
+    }
+
+    /// Returns the blocks inside of the list item, and whether any of them are separated by
+    /// blank lines.
+    fn parse_list_item(&mut self, marker: &ListMarker<'a>) -> (Vec<MarkdownElement<'a>>, bool) {
+        let mut lines = ContainerLines::new(vec![marker.content], self.depth + 1);
+        let mut end = 1;
+        while end < self.lines.len() {
+            let line = self.lines[end];
+            if is_blank(line) {
+                // A list item can begin with at most one blank line
+                if lines.lines.len() == 1 && marker.content.is_empty() {
+                    break;
+                }
+                lines.push(strip_indent(line, marker.content_indent).unwrap_or(""));
+            } else if let Some(stripped) = strip_indent(line, marker.content_indent) {
+                lines.push(stripped);
+            } else if lines.is_lazy_continuation(line) {
+                lines.push(line);
+            } else {
+                break;
+            }
+            end += 1;
+        }
+        let mut lines = lines.lines;
+        // Trailing blank lines separate list items, they aren't a part of them
+        while lines.len() > 1 && is_blank(lines.last().unwrap()) {
+            lines.pop();
+            end -= 1;
+        }
+        self.lines = &self.lines[end..];
+
+        let mut parser = BlockParser::new(&lines, self.references, self.depth + 1);
+        let elements = parser.parse_blocks();
+        self.add_definitions(parser.definitions);
+        (elements, parser.blank_line_between_blocks)
+    }
+
+    /// When a label is defined more than once, the first definition wins.
+    fn add_definitions(&mut self, definitions: References<'a>) {
+        for (label, link) in definitions {
+            self.definitions.entry(label).or_insert(link);
+        }
+    }
+
+    fn parse_table_start_at(&self, index: usize) -> Option<Vec<ColumnAlignment>> {
+        match self.lines[index..] {
+            [header, delimiter, ..] => parse_table_start(header, delimiter),
+            _ => None,
+        }
+    }
+
+    fn parse_table(&mut self, alignments: Vec<ColumnAlignment>) -> MarkdownElement<'a> {
+        let columns = alignments.len();
+        let header = parse_row(self.lines[0], columns, self.references);
+        let end = self.lines[2..]
+            .iter()
+            .position(|line| interrupts_paragraph(line))
+            .map_or(self.lines.len(), |position| position + 2);
+        let rows = self.lines[2..end]
+            .iter()
+            .map(|line| parse_row(line, columns, self.references))
+            .collect();
+        self.lines = &self.lines[end..];
+        MarkdownElement::Table(Table {
+            alignments,
+            header,
+            rows,
+        })
+    }
+
+    /// Parses a paragraph, or a Setext heading if the paragraph lines are
+    /// followed by an `===` or `---` underline.
+    fn parse_paragraph(&mut self) -> MarkdownElement<'a> {
+        let mut end = 1;
+        while end < self.lines.len() {
+            let line = self.lines[end];
+            if let Some(size) = parse_setext_underline(line) {
+                let words = parse_inlines(&self.lines[..end], self.references);
+                self.lines = &self.lines[end + 1..];
+                return MarkdownElement::Heading(Heading { words, size });
+            }
+            if interrupts_paragraph(line) || self.parse_table_start_at(end).is_some() {
+                break;
+            }
+            end += 1;
+        }
+
+        let words = parse_inlines(&self.lines[..end], self.references);
+        self.lines = &self.lines[end..];
+        MarkdownElement::Paragraph(words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paragraph_texts<'a>(elements: &[MarkdownElement<'a>]) -> Vec<Vec<&'a str>> {
+        elements
+            .iter()
+            .map(|element| match element {
+                MarkdownElement::Paragraph(words) => words.iter().map(|word| word.text).collect(),
+                _ => panic!("Expected a paragraph"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn blank_lines_separate_paragraphs() {
+        let markdown = Markdown::parse("one two\nthree\n\n  \nfour\n\n\nfive six\n").unwrap();
+
+        assert_eq!(
+            paragraph_texts(&markdown.elements),
+            vec![
+                vec!["one", "two", "three"],
+                vec!["four"],
+                vec!["five", "six"]
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_input_has_no_elements() {
+        for text in &["", "\n", "   \n\t\n"] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            assert!(markdown.elements.is_empty());
+        }
+    }
+
+    fn heading<'a>(element: &MarkdownElement<'a>) -> (HeadingSize, Vec<&'a str>) {
+        match element {
+            MarkdownElement::Heading(heading) => (
+                heading.size,
+                heading.words.iter().map(|word| word.text).collect(),
+            ),
+            _ => panic!("Expected a heading"),
+        }
+    }
+
+    #[test]
+    fn atx_headings() {
+        for (text, size, words) in &[
+            ("# One", HeadingSize::ExtraLarge, vec!["One"]),
+            ("## Two words", HeadingSize::Large, vec!["Two", "words"]),
+            ("###   Three   ###  ", HeadingSize::Medium, vec!["Three"]),
+            ("   #### Four #", HeadingSize::Small, vec!["Four"]),
+            ("##### Five#", HeadingSize::ExtraSmall, vec!["Five#"]),
+            ("###### C#", HeadingSize::Tiny, vec!["C#"]),
+            ("#", HeadingSize::ExtraLarge, vec![]),
+            ("## ##", HeadingSize::Large, vec![]),
+        ] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            assert_eq!(markdown.elements.len(), 1);
+            assert_eq!(heading(&markdown.elements[0]), (*size, words.clone()));
+        }
+    }
+
+    #[test]
+    fn not_atx_headings() {
+        for text in &["#hashtag", "####### Seven", "    # Indented", "\\# Escaped"] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            assert_eq!(markdown.elements.len(), 1);
+            assert!(!matches!(markdown.elements[0], MarkdownElement::Heading(_)));
+        }
+    }
+
+    #[test]
+    fn setext_headings() {
+        let markdown =
+            Markdown::parse("Big\nheading\n===\n\nSmaller heading\n  ---  \nparagraph").unwrap();
+
+        assert_eq!(markdown.elements.len(), 3);
+        assert_eq!(
+            heading(&markdown.elements[0]),
+            (HeadingSize::ExtraLarge, vec!["Big", "heading"])
+        );
+        assert_eq!(
+            heading(&markdown.elements[1]),
+            (HeadingSize::Large, vec!["Smaller", "heading"])
+        );
+        assert_eq!(
+            paragraph_texts(&markdown.elements[2..]),
+            vec![vec!["paragraph"]]
+        );
+    }
+
+    #[test]
+    fn atx_heading_interrupts_paragraph() {
+        let markdown = Markdown::parse("some text\n# Heading\nmore text").unwrap();
+
+        assert_eq!(markdown.elements.len(), 3);
+        assert_eq!(
+            heading(&markdown.elements[1]),
+            (HeadingSize::ExtraLarge, vec!["Heading"])
+        );
+    }
+
+    fn code_block<'a>(element: &MarkdownElement<'a>) -> (&'a str, Vec<&'a str>) {
+        match element {
+            MarkdownElement::CodeBlock(code_block) => (code_block.info, code_block.lines.clone()),
+            _ => panic!("Expected a code block"),
+        }
+    }
+
+    #[test]
+    fn fenced_code_blocks() {
+        let markdown = Markdown::parse(
+            "```rust\nfn main() {\n    *not* emphasis\n\n}\n```\n~~~~\n~~~\n  ``` \n~~~~~",
+        )
+        .unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert_eq!(
+            code_block(&markdown.elements[0]),
+            ("rust", vec!["fn main() {", "    *not* emphasis", "", "}"])
+        );
+        assert_eq!(
+            code_block(&markdown.elements[1]),
+            ("", vec!["~~~", "  ``` "])
+        );
+    }
+
+    #[test]
+    fn fenced_code_block_indentation() {
+        let markdown = Markdown::parse("  ```\n    indented\n not\nat all\n   ```").unwrap();
+
+        assert_eq!(
+            code_block(&markdown.elements[0]),
+            ("", vec!["  indented", "not", "at all"])
+        );
+    }
+
+    #[test]
+    fn unclosed_fenced_code_block() {
+        let markdown = Markdown::parse("text\n``` sh\nrm -rf /\n\n# Not a heading").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert_eq!(
+            code_block(&markdown.elements[1]),
+            ("sh", vec!["rm -rf /", "", "# Not a heading"])
+        );
+    }
+
+    #[test]
+    fn not_fenced_code_blocks() {
+        for text in &["``", "``` foo`bar"] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            assert_eq!(markdown.elements.len(), 1);
+            assert!(matches!(
+                markdown.elements[0],
+                MarkdownElement::Paragraph(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn indented_code_blocks() {
+        let markdown = Markdown::parse("    one\n\n      two\n\tthree\n    \n\nparagraph").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert_eq!(
+            code_block(&markdown.elements[0]),
+            ("", vec!["one", "", "  two", "three"])
+        );
+    }
+
+    #[test]
+    fn indented_code_doesnt_interrupt_paragraph() {
+        let markdown = Markdown::parse("paragraph\n    continued").unwrap();
+
+        assert_eq!(
+            paragraph_texts(&markdown.elements),
+            vec![vec!["paragraph", "continued"]]
+        );
+    }
+
+    fn list<'e, 'a>(element: &'e MarkdownElement<'a>) -> &'e List<'a> {
+        match element {
+            MarkdownElement::List(list) => list,
+            _ => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    fn tight_unordered_list() {
+        let markdown = Markdown::parse("- one\n- two\n  continued\nlazily\n- three").unwrap();
+
+        assert_eq!(markdown.elements.len(), 1);
+        let list = list(&markdown.elements[0]);
+        assert_eq!(list.kind, ListKind::Unordered);
+        assert!(!list.loose);
+        assert_eq!(list.items.len(), 3);
+        assert_eq!(
+            paragraph_texts(&list.items[1]),
+            vec![vec!["two", "continued", "lazily"]]
+        );
+    }
+
+    #[test]
+    fn ordered_list() {
+        let markdown = Markdown::parse("3) three\n4) four\n1. new list").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert_eq!(
+            list(&markdown.elements[0]).kind,
+            ListKind::Ordered { start: 3 }
+        );
+        assert_eq!(list(&markdown.elements[0]).items.len(), 2);
+        assert_eq!(
+            list(&markdown.elements[1]).kind,
+            ListKind::Ordered { start: 1 }
+        );
+    }
+
+    #[test]
+    fn different_bullets_start_new_lists() {
+        let markdown = Markdown::parse("- a\n- b\n+ c\n* d").unwrap();
+
+        assert_eq!(markdown.elements.len(), 3);
+    }
+
+    #[test]
+    fn nested_lists() {
+        let markdown = Markdown::parse("- a\n  - b\n    1. c\n- d").unwrap();
+
+        let outer = list(&markdown.elements[0]);
+        assert_eq!(outer.items.len(), 2);
+        assert_eq!(paragraph_texts(&outer.items[0][..1]), vec![vec!["a"]]);
+        let inner = list(&outer.items[0][1]);
+        assert_eq!(inner.kind, ListKind::Unordered);
+        assert_eq!(paragraph_texts(&inner.items[0][..1]), vec![vec!["b"]]);
+        let innermost = list(&inner.items[0][1]);
+        assert_eq!(innermost.kind, ListKind::Ordered { start: 1 });
+    }
+
+    #[test]
+    fn loose_lists() {
+        for text in &["- a\n\n- b", "- a\n\n  b\n- c", "1. a\n\n\n2. b"] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            assert_eq!(markdown.elements.len(), 1, "{:?}", text);
+            assert!(
+                list(&markdown.elements[0]).loose,
+                "{:?} should be loose",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn blank_lines_in_nested_lists_dont_loosen_outer_list() {
+        let markdown = Markdown::parse("- a\n  - b\n\n  - c\n- d").unwrap();
+
+        let outer = list(&markdown.elements[0]);
+        assert!(!outer.loose);
+        assert!(list(&outer.items[0][1]).loose);
+    }
+
+    #[test]
+    fn list_item_content() {
+        let markdown =
+            Markdown::parse("1.  para\n\n    ```\n    code\n    ```\n-\n  empty start\n-").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        let ordered = list(&markdown.elements[0]);
+        assert!(matches!(ordered.items[0][1], MarkdownElement::CodeBlock(_)));
+        let unordered = list(&markdown.elements[1]);
+        assert_eq!(unordered.items.len(), 2);
+        assert_eq!(
+            paragraph_texts(&unordered.items[0]),
+            vec![vec!["empty", "start"]]
+        );
+        assert!(unordered.items[1].is_empty());
+    }
+
+    #[test]
+    fn list_interrupting_paragraph() {
+        let markdown = Markdown::parse("text\n- item\n\ntext\n2. not an item").unwrap();
+
+        assert_eq!(markdown.elements.len(), 3);
+        assert_eq!(
+            paragraph_texts(&markdown.elements[2..]),
+            vec![vec!["text", "2.", "not", "an", "item"]]
+        );
+    }
+
+    #[test]
+    fn not_list_items() {
+        for text in &["-not", "1234567890. too long", "*emphasis*"] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            assert!(matches!(
+                markdown.elements[0],
+                MarkdownElement::Paragraph(_)
+            ));
+        }
+    }
+
+    fn block_quote<'e, 'a>(element: &'e MarkdownElement<'a>) -> &'e [MarkdownElement<'a>] {
+        match element {
+            MarkdownElement::BlockQuote(elements) => elements,
+            _ => panic!("Expected a block quote"),
+        }
+    }
+
+    #[test]
+    fn block_quotes() {
+        let markdown = Markdown::parse("> one\n>two\nlazy\n>\n> # Heading\n\nafter").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        let quote = block_quote(&markdown.elements[0]);
+        assert_eq!(quote.len(), 2);
+        assert_eq!(
+            paragraph_texts(&quote[..1]),
+            vec![vec!["one", "two", "lazy"]]
+        );
+        assert!(matches!(quote[1], MarkdownElement::Heading(_)));
+    }
+
+    #[test]
+    fn nested_block_quotes() {
+        let markdown = Markdown::parse("> a\n> > b\n> > - c\n> d").unwrap();
+
+        let outer = block_quote(&markdown.elements[0]);
+        assert_eq!(outer.len(), 2);
+        let inner = block_quote(&outer[1]);
+        assert_eq!(paragraph_texts(&inner[..1]), vec![vec!["b"]]);
+        assert!(matches!(inner[1], MarkdownElement::List(_)));
+        // `d` is a lazy continuation of the paragraph inside of the list
+        assert_eq!(inner.len(), 2);
+    }
+
+    #[test]
+    fn block_quote_contents() {
+        let markdown = Markdown::parse(">     code\n> ```\n> fenced\n> ```\n> 1. item").unwrap();
+
+        let quote = block_quote(&markdown.elements[0]);
+        assert_eq!(quote.len(), 3);
+        assert_eq!(code_block(&quote[0]), ("", vec!["code"]));
+        assert_eq!(code_block(&quote[1]), ("", vec!["fenced"]));
+        assert!(matches!(quote[2], MarkdownElement::List(_)));
+    }
+
+    #[test]
+    fn lazy_lines_dont_continue_code() {
+        let markdown = Markdown::parse("> ```\n> code\nnot code").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+    }
+
+    #[test]
+    fn long_lazy_continuations() {
+        // Parsing the whole container again for every lazy line took minutes for these
+        let lazy_lines = "lazy\n".repeat(20_000);
+        for start in &["> - a\n", "> > > a\n", "- a\n  - b\n    - c\n"] {
+            let text = format!("{}{}", start, lazy_lines);
+            let markdown = Markdown::parse(&text).unwrap();
+
+            assert_eq!(markdown.elements.len(), 1);
+            let mut elements = &markdown.elements[..];
+            let words = loop {
+                match elements.last() {
+                    Some(MarkdownElement::BlockQuote(quote)) => elements = quote,
+                    Some(MarkdownElement::List(list)) => elements = list.items.last().unwrap(),
+                    Some(MarkdownElement::Paragraph(words)) => break words,
+                    _ => panic!("Expected a paragraph"),
+                }
+            };
+            assert_eq!(words.len(), 20_001);
+        }
+    }
+
+    #[test]
+    fn long_lazy_continuations_with_pipes() {
+        let text = format!("> a\n{}", "b | c\n".repeat(20_000));
+        let markdown = Markdown::parse(&text).unwrap();
+
+        match &markdown.elements[..] {
+            [MarkdownElement::BlockQuote(quote)] => match &quote[..] {
+                [MarkdownElement::Paragraph(words)] => assert_eq!(words.len(), 60_001),
+                _ => panic!("Expected a paragraph"),
+            },
+            _ => panic!("Expected a block quote"),
+        }
+    }
+
+    #[test]
+    fn table_in_block_quote_after_paragraph() {
+        let markdown = Markdown::parse("> a\n> b | c\n> - | -\n> d | e").unwrap();
+
+        match &markdown.elements[..] {
+            [MarkdownElement::BlockQuote(quote)] => match &quote[..] {
+                [MarkdownElement::Paragraph(_), MarkdownElement::Table(table)] => {
+                    assert_eq!(table.rows.len(), 1)
+                }
+                _ => panic!("Expected a paragraph and a table"),
+            },
+            _ => panic!("Expected a block quote"),
+        }
+    }
+
+    #[test]
+    fn nesting_depth_is_capped() {
+        let lists: String = (0..2000)
+            .map(|depth| format!("{}- a\n", "  ".repeat(depth)))
+            .collect();
+        let quotes: String = (1..=2000)
+            .map(|depth| format!("{}a\n", "> ".repeat(depth)))
+            .collect();
+        for text in &[lists, quotes] {
+            let markdown = Markdown::parse(text).unwrap();
+
+            let mut elements = &markdown.elements[..];
+            let mut depth = 0;
+            let words = loop {
+                match elements.last() {
+                    Some(MarkdownElement::BlockQuote(quote)) => elements = quote,
+                    Some(MarkdownElement::List(list)) => elements = list.items.last().unwrap(),
+                    Some(MarkdownElement::Paragraph(words)) => break words,
+                    _ => panic!("Expected a paragraph"),
+                }
+                depth += 1;
+            };
+            assert_eq!(depth, MAX_NESTING);
+            assert!(words
+                .iter()
+                .any(|word| word.text == ">" || word.text == "-"));
+        }
+    }
+
+    #[test]
+    fn block_quote_interrupts_paragraph() {
+        let markdown = Markdown::parse("text\n> quote").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert!(matches!(
+            markdown.elements[1],
+            MarkdownElement::BlockQuote(_)
+        ));
+    }
+
+    #[test]
+    fn blank_line_ends_block_quote() {
+        let markdown = Markdown::parse("> one\n\n> two").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+    }
+
+    #[test]
+    fn tables() {
+        let markdown =
+            Markdown::parse("| a | b |\n|:--|--:|\n| 1 | 2 |\n3 | 4 | 5\n|6|\n\nafter").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        let table = match &markdown.elements[0] {
+            MarkdownElement::Table(table) => table,
+            _ => panic!("Expected a table"),
+        };
+        assert_eq!(
+            table.alignments,
+            vec![ColumnAlignment::Left, ColumnAlignment::Right]
+        );
+        assert_eq!(table.header.len(), 2);
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.rows[1][1][0].text, "4");
+        assert!(table.rows[2][1].is_empty());
+    }
+
+    #[test]
+    fn table_ends_at_other_blocks() {
+        let markdown = Markdown::parse("a | b\n--|--\nc | d\n# Heading").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert!(matches!(markdown.elements[1], MarkdownElement::Heading(_)));
+    }
+
+    #[test]
+    fn table_interrupts_paragraph() {
+        let markdown = Markdown::parse("text\na | b\n--|--").unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        assert_eq!(paragraph_texts(&markdown.elements[..1]), vec![vec!["text"]]);
+        assert!(matches!(markdown.elements[1], MarkdownElement::Table(_)));
+    }
+
+    #[test]
+    fn reference_definitions_are_not_rendered() {
+        let markdown =
+            Markdown::parse("[foo] and [bar]\n\n> [FOO]: /first\n\n[foo]: /second \"title\"")
+                .unwrap();
+
+        assert_eq!(markdown.elements.len(), 2);
+        let words = match &markdown.elements[0] {
+            MarkdownElement::Paragraph(words) => words,
+            _ => panic!("Expected a paragraph"),
+        };
+        assert_eq!(
+            words[0].link,
+            Some(Link {
+                destination: "/first",
+                title: None
+            })
+        );
+        assert_eq!(words[2].text, "[bar]");
+        assert_eq!(block_quote(&markdown.elements[1]).len(), 0);
+    }
+}
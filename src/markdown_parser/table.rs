@@ -0,0 +1,129 @@
+use super::{inline::parse_inlines, link::References, ColumnAlignment, TableCell};
+
+/// Splits a table row on unescaped pipes. The pipes at the start and at the end of the row
+/// are optional.
+fn split_row(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = match line.strip_suffix('|') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => line,
+    };
+
+    let mut cells = Vec::new();
+    let mut cell_start = 0;
+    let mut escaped = false;
+    for (i, b) in line.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'|' => {
+                cells.push(line[cell_start..i].trim());
+                cell_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    cells.push(line[cell_start..].trim());
+    cells
+}
+
+/// A delimiter row has a cell of dashes for each column, like `| --- | :---: |`.
+pub fn parse_delimiter_row(line: &str) -> Option<Vec<ColumnAlignment>> {
+    if !line.contains('|') {
+        return None;
+    }
+    split_row(line)
+        .into_iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.ends_with(':');
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.bytes().all(|b| b == b'-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (true, true) => ColumnAlignment::Center,
+                (false, true) => ColumnAlignment::Right,
+                _ => ColumnAlignment::Left,
+            })
+        })
+        .collect()
+}
+
+/// A table starts with a header row, followed by a delimiter row with the same number of
+/// cells, like `| --- | :---: |`.
+pub fn parse_table_start(header: &str, delimiter: &str) -> Option<Vec<ColumnAlignment>> {
+    if !header.contains('|') {
+        return None;
+    }
+    let alignments = parse_delimiter_row(delimiter)?;
+    if split_row(header).len() == alignments.len() {
+        Some(alignments)
+    } else {
+        None
+    }
+}
+
+/// Rows with too few cells are padded with empty cells, and excess cells are ignored.
+pub fn parse_row<'a>(
+    line: &'a str,
+    columns: usize,
+    references: &References<'a>,
+) -> Vec<TableCell<'a>> {
+    let mut cells: Vec<TableCell<'a>> = split_row(line)
+        .into_iter()
+        .take(columns)
+        .map(|cell| parse_inlines(&[cell], references))
+        .collect();
+    cells.resize_with(columns, Vec::new);
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_split_on_unescaped_pipes() {
+        assert_eq!(split_row("| a | b |"), vec!["a", "b"]);
+        assert_eq!(split_row("a|b"), vec!["a", "b"]);
+        assert_eq!(split_row("| a \\| b |"), vec!["a \\| b"]);
+        assert_eq!(split_row("| a | b \\|"), vec!["a", "b \\|"]);
+        assert_eq!(split_row("|"), vec![""]);
+    }
+
+    #[test]
+    fn delimiter_rows() {
+        assert_eq!(
+            parse_delimiter_row("| --- | :-- | --: | :-: |"),
+            Some(vec![
+                ColumnAlignment::Left,
+                ColumnAlignment::Left,
+                ColumnAlignment::Right,
+                ColumnAlignment::Center
+            ])
+        );
+        assert_eq!(parse_delimiter_row("| --- | - -- |"), None);
+        assert_eq!(parse_delimiter_row("| --- | :: |"), None);
+        assert_eq!(parse_delimiter_row("| --- | |"), None);
+    }
+
+    #[test]
+    fn table_start_needs_matching_cell_count() {
+        assert!(parse_table_start("a | b", "--|--").is_some());
+        assert!(parse_table_start("a | b", "--|--|--").is_none());
+        assert!(parse_table_start("a", "---").is_none());
+    }
+
+    #[test]
+    fn rows_are_padded_and_truncated() {
+        let row = parse_row("| a | *b* c |", 3, &References::new());
+        assert_eq!(row.len(), 3);
+        assert_eq!(row[1].len(), 2);
+        assert!(row[1][0].style.italic);
+        assert!(row[2].is_empty());
+
+        assert_eq!(parse_row("a | b | c", 2, &References::new()).len(), 2);
+    }
+}
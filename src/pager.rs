@@ -0,0 +1,191 @@
+use crate::{
+    common::Dimensions,
+    keybindings::{KeybindingResoluton, Keybindings, KeybindingsBuilder, KeybindingsBuilderError},
+    renderer::{Renderer, RendererResult},
+    terminal::{Key, TerminalEvent, TerminalEvents},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    LineDown,
+    LineUp,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    CycleAlignment,
+    ToggleLineBreaking,
+    Quit,
+}
+
+fn ctrl(character: char) -> Key {
+    Key {
+        character,
+        control: true,
+        shift: false,
+    }
+}
+
+fn shift(character: char) -> Key {
+    Key {
+        character,
+        control: false,
+        shift: true,
+    }
+}
+
+/// The keys work like in `less`.
+pub fn default_keybindings() -> Result<Keybindings<Action>, KeybindingsBuilderError> {
+    let bindings = [
+        (vec!['j'.into()], Action::LineDown),
+        (vec!['e'.into()], Action::LineDown),
+        (vec![ctrl('n')], Action::LineDown),
+        (vec!['k'.into()], Action::LineUp),
+        (vec!['y'.into()], Action::LineUp),
+        (vec![ctrl('p')], Action::LineUp),
+        (vec!['d'.into()], Action::HalfPageDown),
+        (vec![ctrl('d')], Action::HalfPageDown),
+        (vec!['u'.into()], Action::HalfPageUp),
+        (vec![ctrl('u')], Action::HalfPageUp),
+        (vec![' '.into()], Action::PageDown),
+        (vec!['f'.into()], Action::PageDown),
+        (vec![ctrl('f')], Action::PageDown),
+        (vec!['b'.into()], Action::PageUp),
+        (vec![ctrl('b')], Action::PageUp),
+        (vec!['g'.into(), 'g'.into()], Action::Top),
+        (vec!['<'.into()], Action::Top),
+        // Not every terminal reports shift for capital letters
+        (vec!['G'.into()], Action::Bottom),
+        (vec![shift('G')], Action::Bottom),
+        (vec!['>'.into()], Action::Bottom),
+        (vec!['a'.into()], Action::CycleAlignment),
+        (vec!['w'.into()], Action::ToggleLineBreaking),
+        (vec!['q'.into()], Action::Quit),
+        (vec![ctrl('c')], Action::Quit),
+    ];
+    let mut builder = KeybindingsBuilder::new();
+    for (keys, action) in bindings.iter() {
+        builder = builder.add_keybinding(keys, *action)?;
+    }
+    Ok(builder.build())
+}
+
+// Adds `key` to the keys pressed so far, and returns the action once they make up a whole
+// keybinding
+fn resolve_key(keybindings: &Keybindings<Action>, keys: &mut Vec<Key>, key: Key) -> Option<Action> {
+    keys.push(key);
+    match keybindings.resolve_keys(keys) {
+        KeybindingResoluton::Resolved(&action) => {
+            keys.clear();
+            Some(action)
+        }
+        KeybindingResoluton::AwaitingNextKey => None,
+        KeybindingResoluton::NoKeybinding if keys.len() > 1 => {
+            // The last key doesn't go with the ones before it, but may still mean something
+            // on its own, like `j` after `g`
+            let key = keys.pop().unwrap();
+            keys.clear();
+            resolve_key(keybindings, keys, key)
+        }
+        KeybindingResoluton::NoKeybinding => {
+            keys.clear();
+            None
+        }
+    }
+}
+
+/// Shows the document until the user quits.
+pub fn run(
+    renderer: &mut Renderer,
+    events: &TerminalEvents,
+    keybindings: &Keybindings<Action>,
+) -> RendererResult<()> {
+    let mut keys = Vec::new();
+    renderer.paint()?;
+    loop {
+        let key = match events.next_event()? {
+            TerminalEvent::Key(key) => key,
+            TerminalEvent::Resize { width, height } => {
+                renderer.resize(Dimensions {
+                    width: width as usize,
+                    height: height as usize,
+                });
+                renderer.paint()?;
+                continue;
+            }
+        };
+        let action = match resolve_key(keybindings, &mut keys, key) {
+            Some(action) => action,
+            None => continue,
+        };
+
+        let page = renderer.height().max(1);
+        let half_page = (page / 2).max(1);
+        match action {
+            Action::LineDown => renderer.scroll_down(1),
+            Action::LineUp => renderer.scroll_up(1),
+            Action::HalfPageDown => renderer.scroll_down(half_page),
+            Action::HalfPageUp => renderer.scroll_up(half_page),
+            Action::PageDown => renderer.scroll_down(page),
+            Action::PageUp => renderer.scroll_up(page),
+            Action::Top => renderer.scroll_to(0),
+            Action::Bottom => renderer.scroll_to(usize::MAX),
+            Action::CycleAlignment => renderer.cycle_alignment(),
+            Action::ToggleLineBreaking => renderer.toggle_line_breaking(),
+            Action::Quit => return Ok(()),
+        }
+        renderer.paint()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keybindings_are_unique() {
+        assert!(default_keybindings().is_ok());
+    }
+
+    #[test]
+    fn default_keybindings_resolve() {
+        let keybindings = default_keybindings().unwrap();
+
+        assert_eq!(
+            keybindings.resolve_keys(&['g'.into()]),
+            KeybindingResoluton::AwaitingNextKey
+        );
+        assert_eq!(
+            keybindings.resolve_keys(&['g'.into(), 'g'.into()]),
+            KeybindingResoluton::Resolved(&Action::Top)
+        );
+        assert_eq!(
+            keybindings.resolve_keys(&[ctrl('d')]),
+            KeybindingResoluton::Resolved(&Action::HalfPageDown)
+        );
+        assert_eq!(
+            keybindings.resolve_keys(&['x'.into()]),
+            KeybindingResoluton::NoKeybinding
+        );
+    }
+
+    #[test]
+    fn keys_after_an_unfinished_keybinding() {
+        let keybindings = default_keybindings().unwrap();
+        let mut keys = Vec::new();
+        let mut press = |character: char| resolve_key(&keybindings, &mut keys, character.into());
+
+        assert_eq!(press('g'), None);
+        assert_eq!(press('g'), Some(Action::Top));
+        assert_eq!(press('g'), None);
+        assert_eq!(press('j'), Some(Action::LineDown));
+        assert_eq!(press('g'), None);
+        assert_eq!(press('x'), None);
+        assert_eq!(press('j'), Some(Action::LineDown));
+        // An unfinished keybinding starts over
+        assert_eq!(press('g'), None);
+        assert_eq!(press('g'), Some(Action::Top));
+    }
+}
@@ -0,0 +1,61 @@
+use hypher::{hyphenate, Lang};
+
+/// Returns the byte offsets in `word` where it can be hyphenated, found with Liang's
+/// algorithm and the TeX patterns for English. Punctuation around the word is left alone,
+/// and words with anything other than letters in them, like URLs, aren't hyphenated at all.
+pub fn hyphenation_points(word: &str) -> Vec<usize> {
+    let start = word.len() - word.trim_start_matches(|c: char| !c.is_alphabetic()).len();
+    let core = word[start..].trim_end_matches(|c: char| !c.is_alphabetic());
+    if core.is_empty() || !core.chars().all(char::is_alphabetic) {
+        return Vec::new();
+    }
+
+    let mut points: Vec<usize> = hyphenate(core, Lang::English)
+        .scan(start, |offset, syllable| {
+            *offset += syllable.len();
+            Some(*offset)
+        })
+        .collect();
+    // The end of the last syllable is the end of the word
+    points.pop();
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syllables(word: &str) -> Vec<&str> {
+        let mut start = 0;
+        let mut syllables = Vec::new();
+        for point in hyphenation_points(word) {
+            syllables.push(&word[start..point]);
+            start = point;
+        }
+        syllables.push(&word[start..]);
+        syllables
+    }
+
+    #[test]
+    fn words_are_split_into_syllables() {
+        assert_eq!(syllables("hyphenation"), vec!["hy", "phen", "ation"]);
+        assert_eq!(syllables("Extensive"), vec!["Ex", "ten", "sive"]);
+        assert_eq!(syllables("a"), vec!["a"]);
+    }
+
+    #[test]
+    fn punctuation_is_kept_out_of_syllables() {
+        assert_eq!(syllables("(extensive),"), vec!["(ex", "ten", "sive),"]);
+        assert_eq!(
+            syllables("\"hyphenation\""),
+            vec!["\"hy", "phen", "ation\""]
+        );
+    }
+
+    #[test]
+    fn only_words_are_hyphenated() {
+        assert!(hyphenation_points("https://example.com/extensive").is_empty());
+        assert!(hyphenation_points("TurboPascal/C/Asm").is_empty());
+        assert!(hyphenation_points("...").is_empty());
+    }
+}
@@ -1,6 +1,12 @@
-use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::ops::Range;
 
-use crate::markdown_parser::StyledWord;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::markdown_parser::{ColumnAlignment, Separator, StyledWord};
+
+use super::hyphenation::hyphenation_points;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct LayoutLine<'a> {
@@ -13,189 +19,677 @@ impl<'a> LayoutLine<'a> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineBreaking {
+    /// Puts as many words on each line as fit, one line at a time.
+    FirstFit,
+    /// Chooses the breaks that make the spacing as even as possible across the whole
+    /// paragraph, like Knuth and Plass's algorithm in TeX.
+    TotalFit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// Stretches the spaces so that every line but the last one fills the whole width.
+    Justify,
+    Left,
+    Right,
+    Center,
+}
+
+impl Alignment {
+    /// The alignment that comes after this one when cycling through all of them.
+    pub fn next(self) -> Self {
+        match self {
+            Alignment::Justify => Alignment::Left,
+            Alignment::Left => Alignment::Right,
+            Alignment::Right => Alignment::Center,
+            Alignment::Center => Alignment::Justify,
+        }
+    }
+}
+
+impl LineBreaking {
+    /// The other way of breaking lines.
+    pub fn toggle(self) -> Self {
+        match self {
+            LineBreaking::FirstFit => LineBreaking::TotalFit,
+            LineBreaking::TotalFit => LineBreaking::FirstFit,
+        }
+    }
+}
+
+impl From<ColumnAlignment> for Alignment {
+    fn from(alignment: ColumnAlignment) -> Self {
+        match alignment {
+            ColumnAlignment::Left => Alignment::Left,
+            ColumnAlignment::Center => Alignment::Center,
+            ColumnAlignment::Right => Alignment::Right,
+        }
+    }
+}
+
+pub struct LayoutOptions {
+    /// How paragraphs are aligned. Some other elements, like headings, have their own
+    /// alignment.
+    pub alignment: Alignment,
+    pub line_breaking: LineBreaking,
+    /// Text is never laid out wider than this, no matter how wide the screen is.
+    pub max_width: Option<usize>,
+    /// Whether words may be hyphenated to even out the spacing. Words that don't fit on a
+    /// line at all are always hyphenated.
+    pub hyphenation: bool,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            alignment: Alignment::Justify,
+            line_breaking: LineBreaking::TotalFit,
+            max_width: Some(80),
+            hyphenation: true,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum LayoutElement<'a> {
     Word(StyledWord<'a>),
     Whitespace(usize),
 }
 
-fn styled_word_length(word: &StyledWord) -> usize {
-    word.text.len()
+// Terminals draw a whole grapheme cluster in one or two cells, even if it's made of several
+// wide characters, like emoji joined with zero width joiners
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().min(2)
 }
 
-fn split_styled_word<'a>(word: &StyledWord<'a>, index: usize) -> (StyledWord<'a>, StyledWord<'a>) {
-    let (t1, t2) = word.text.split_at(index);
-    (
-        StyledWord {
-            text: t1,
-            style: word.style,
-        },
-        StyledWord {
-            text: t2,
-            style: word.style,
-        },
-    )
+/// The number of terminal cells the text takes up.
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Splits the text so that the first part takes up at most `width` cells, without breaking
+/// up grapheme clusters. The first part always gets at least one grapheme cluster, even if
+/// it's wider than `width`.
+pub fn split_at_width(text: &str, width: usize) -> (&str, &str) {
+    let mut used = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > width && i > 0 {
+            return text.split_at(i);
+        }
+    }
+    (text, "")
+}
+
+// Links are only wrapped in escape sequences when printed, the destination never ends up
+// on screen
+pub fn styled_word_length(word: &StyledWord) -> usize {
+    text_width(word.text)
+}
+
+// The words that are made of several fragments, in the order of the fragments
+fn glued_words<'w, 'a>(words: &'w [StyledWord<'a>]) -> impl Iterator<Item = &'w [StyledWord<'a>]> {
+    words.split_inclusive(|word| word.separator != Separator::Nothing)
+}
+
+fn fragments_width(fragments: &[StyledWord]) -> usize {
+    fragments.iter().map(styled_word_length).sum()
+}
+
+/// The widths of the words, counting fragments without spaces between them, like in
+/// `**bold**word`, as a single word.
+pub fn word_widths(words: &[StyledWord]) -> Vec<usize> {
+    glued_words(words).map(fragments_width).collect()
 }
 
 struct WordsInLine<'a> {
-    words: Vec<StyledWord<'a>>,
+    // Each word is a list of fragments in different styles
+    words: Vec<Vec<StyledWord<'a>>>,
+    // The width of each gap between the words before it's stretched, which is one space,
+    // or nothing between the characters of scripts that don't use spaces, like Chinese
+    gaps: Vec<usize>,
     remaining_space: usize,
+    // The last word is cut off at a hyphenation point, and continues on the next line
+    hyphenated: bool,
 }
 
 impl<'a> WordsInLine<'a> {
-    fn spread_evenly(&self) -> LayoutLine<'a> {
-        assert!(self.words.len() > 0);
+    fn push_word(&self, layout_line: &mut LayoutLine<'a>, i: usize) {
+        let fragments = &self.words[i];
+        for &fragment in fragments {
+            layout_line.elements.push(LayoutElement::Word(fragment));
+        }
+        if self.hyphenated && i == self.words.len() - 1 {
+            let last = *fragments.last().unwrap();
+            layout_line
+                .elements
+                .push(LayoutElement::Word(StyledWord { text: "-", ..last }));
+        }
+    }
+
+    /// Stretches the gaps between the words so that the line fills the whole width. The
+    /// spaces that can't be split evenly go to the gaps after punctuation first, and stay
+    /// away from the wider gaps of the line above, so that wide gaps don't line up into
+    /// "rivers" running down the paragraph. `wide_gaps` has the columns of the wider gaps of
+    /// the line above, and gets replaced with the ones of this line.
+    fn spread_evenly(
+        &self,
+        line_index: usize,
+        wide_gaps: &mut Vec<Range<usize>>,
+    ) -> LayoutLine<'a> {
+        assert!(!self.words.is_empty());
 
         if self.words.len() == 1 {
+            wide_gaps.clear();
             return self.align_left();
         }
 
-        let mut layout_line = LayoutLine::new(Vec::new());
         let gaps_between_words = self.words.len() - 1;
-        let spaces_per_gap = self.remaining_space / gaps_between_words;
-        let num_extra_spaces = self.remaining_space - gaps_between_words * spaces_per_gap;
-        assert!(num_extra_spaces <= gaps_between_words);
-
-        let mut extra_spaces = get_unique_random_numbers(num_extra_spaces, 0, gaps_between_words);
-
-        for (i, word) in self.words.iter().enumerate() {
-            let mut add_word = || layout_line.elements.push(LayoutElement::Word(*word));
+        let stretch_per_gap = self.remaining_space / gaps_between_words;
+        let num_extra_spaces = self.remaining_space % gaps_between_words;
 
-            add_word();
+        // Where the gaps would be without the extra spaces
+        let mut column = 0;
+        let gap_columns: Vec<Range<usize>> = self
+            .words
+            .iter()
+            .zip(self.gaps.iter())
+            .map(|(word, &gap)| {
+                column += fragments_width(word);
+                let gap = column..column + gap + stretch_per_gap;
+                column = gap.end;
+                gap
+            })
+            .collect();
 
-            let mut add_whitespace = || {
-                if i == self.words.len() - 1 {
-                    return;
+        // Ties go to the left on every other line, and to the right on the others
+        let order: Vec<usize> = if line_index.is_multiple_of(2) {
+            (0..gaps_between_words).collect()
+        } else {
+            (0..gaps_between_words).rev().collect()
+        };
+        let mut has_extra_space = vec![false; gaps_between_words];
+        for _ in 0..num_extra_spaces {
+            let cost = |&i: &usize| {
+                let mut cost = 0;
+                if self.words[i].last().unwrap().text.ends_with(PUNCTUATION) {
+                    cost -= 2;
                 }
-
-                if let Some(&idx) = extra_spaces.last() {
-                    if i == idx {
-                        layout_line
-                            .elements
-                            .push(LayoutElement::Whitespace(spaces_per_gap + 2));
-                        // Above it says +2 because there are some extra spaces left,
-                        // so we're adding them to gaps one-by-one.
-                        // In both arms of the if statement, we add +1 because there must be at
-                        // least one space in each gap.
-                        extra_spaces.pop();
-                        return;
-                    }
+                if wide_gaps.iter().any(|gap| overlaps(gap, &gap_columns[i])) {
+                    cost += 3;
                 }
-
-                layout_line
-                    .elements
-                    .push(LayoutElement::Whitespace(spaces_per_gap + 1));
+                let neighbours = [i.checked_sub(1), Some(i + 1)];
+                if neighbours
+                    .iter()
+                    .flatten()
+                    .any(|&j| has_extra_space.get(j) == Some(&true))
+                {
+                    cost += 1;
+                }
+                cost
             };
+            let best = order
+                .iter()
+                .copied()
+                .filter(|&i| !has_extra_space[i])
+                .min_by_key(cost)
+                .unwrap();
+            has_extra_space[best] = true;
+        }
 
-            add_whitespace();
+        let mut layout_line = LayoutLine::new(Vec::new());
+        let mut column = 0;
+        wide_gaps.clear();
+        for (i, word) in self.words.iter().enumerate() {
+            self.push_word(&mut layout_line, i);
+            column += fragments_width(word);
+            let spaces = match has_extra_space.get(i) {
+                Some(true) => {
+                    let spaces = self.gaps[i] + stretch_per_gap + 1;
+                    wide_gaps.push(column..column + spaces);
+                    spaces
+                }
+                Some(false) => self.gaps[i] + stretch_per_gap,
+                // The last word
+                None => break,
+            };
+            if spaces > 0 {
+                layout_line.elements.push(LayoutElement::Whitespace(spaces));
+            }
+            column += spaces;
         }
         layout_line
     }
 
     fn align_left(&self) -> LayoutLine<'a> {
+        self.align_with_padding(0)
+    }
+
+    fn align_right(&self) -> LayoutLine<'a> {
+        self.align_with_padding(self.remaining_space)
+    }
+
+    fn align_center(&self) -> LayoutLine<'a> {
+        self.align_with_padding(self.remaining_space / 2)
+    }
+
+    /// Puts `left_padding` of the remaining space before the words, and the rest after them.
+    fn align_with_padding(&self, left_padding: usize) -> LayoutLine<'a> {
         let mut layout_line = LayoutLine::new(Vec::new());
-        for (i, word) in self.words.iter().enumerate() {
-            layout_line.elements.push(LayoutElement::Word(*word));
-            if i != self.words.len() - 1 {
-                layout_line.elements.push(LayoutElement::Whitespace(1));
+        if left_padding > 0 {
+            layout_line
+                .elements
+                .push(LayoutElement::Whitespace(left_padding));
+        }
+        for i in 0..self.words.len() {
+            self.push_word(&mut layout_line, i);
+            match self.gaps.get(i) {
+                Some(&gap) if gap > 0 => layout_line.elements.push(LayoutElement::Whitespace(gap)),
+                _ => {}
             }
         }
-        if self.remaining_space > 0 {
+        let right_padding = self.remaining_space - left_padding;
+        if right_padding > 0 {
             layout_line
                 .elements
-                .push(LayoutElement::Whitespace(self.remaining_space));
+                .push(LayoutElement::Whitespace(right_padding));
         }
         layout_line
     }
 }
 
-fn get_unique_random_numbers(count: usize, start: usize, end: usize) -> Vec<usize> {
-    assert!(end >= start);
-    assert!(count <= end - start);
-    const SEED: [u8; 32] = [
-        112, 111, 8, 251, 183, 240, 224, 102, 93, 80, 201, 131, 121, 56, 179, 229, 173, 121, 174,
-        140, 110, 128, 175, 230, 32, 98, 16, 147, 254, 24, 1, 86,
-    ];
+// Punctuation that ends a clause, where a wider gap looks natural
+const PUNCTUATION: [char; 7] = ['.', ',', ';', ':', '!', '?', ')'];
 
-    let mut numbers: Vec<_> = (start..end).collect();
-    let mut rng = SmallRng::from_seed(SEED);
-    for i in 0..count {
-        let index_to_swap = rng.gen_range(0..numbers.len() - i);
-        numbers.swap(i, index_to_swap);
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+// What separates a piece of a word from the next piece
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Join {
+    // The pieces are separate words
+    Space,
+    // The pieces are characters of a script that doesn't use spaces, like Chinese, so the
+    // line can end between them, and the gap between them can be stretched like a space
+    Character,
+    // The line can end between the pieces without a hyphen, like after a slash
+    Break,
+    // The pieces are fragments of the same word in different styles, so the line can't
+    // end between them
+    Glue,
+    // The pieces are syllables of the same word, so a line that ends between them gets a
+    // hyphen
+    Hyphen,
+    // The word doesn't fit on a line even when hyphenated, so it's cut up without a marker
+    Cut,
+}
+
+// The smallest parts that the line breakers work with
+struct Piece {
+    fragment: usize,
+    range: Range<usize>,
+    width: usize,
+    join: Join,
+}
+
+fn split_into_pieces(screen_width: usize, words: &[StyledWord], hyphenation: bool) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut index = 0;
+    for fragments in glued_words(words) {
+        // A word that doesn't fit on a line can be broken between its fragments
+        let too_long = fragments_width(fragments) > screen_width;
+        // Soft hyphens tell where the word can be hyphenated better than the patterns do
+        let soft_hyphens = fragments
+            .iter()
+            .any(|fragment| fragment.text.contains(SOFT_HYPHEN));
+        for (i, word) in fragments.iter().enumerate() {
+            let join = match fragments.get(i + 1) {
+                None => Join::Space,
+                Some(next) => {
+                    let last = word.text.graphemes(true).next_back().unwrap_or("");
+                    let first = next.text.graphemes(true).next().unwrap_or("");
+                    let pair = [last, first].concat();
+                    let join = break_opportunities(&pair).next().map(|(_, join)| join);
+                    join.unwrap_or(if too_long { Join::Cut } else { Join::Glue })
+                }
+            };
+            split_fragment(
+                screen_width,
+                index,
+                word,
+                hyphenation && !soft_hyphens,
+                join,
+                &mut pieces,
+            );
+            index += 1;
+        }
     }
-    numbers.resize(count, 0);
-    numbers.sort();
-    numbers.reverse();
-    numbers
+    pieces
 }
 
-// This has to respect flow, so it can't happen first.
-// It has to happen during layout calculation. Otherwise it's retarded.
-fn split_words_longer_than_screen_width<'a>(
-    screen_width: usize,
-    words: &[StyledWord<'a>],
-) -> Vec<StyledWord<'a>> {
-    words
-        .iter()
-        .flat_map(|word| {
-            let mut word = word.clone();
-            let mut r = Vec::new();
-            while styled_word_length(&word) > screen_width {
-                let (w, remainder) = split_styled_word(&word, screen_width);
-                r.push(w);
-                word = remainder;
+// The places inside the text where a line can end, according to the Unicode line breaking
+// algorithm
+fn break_opportunities(text: &str) -> impl Iterator<Item = (usize, Join)> + '_ {
+    linebreaks(text)
+        .filter(move |&(i, opportunity)| opportunity == BreakOpportunity::Allowed && i < text.len())
+        .map(move |(i, _)| {
+            let before = text[..i].graphemes(true).next_back();
+            let after = text[i..].graphemes(true).next();
+            if text[..i].ends_with(SOFT_HYPHEN) {
+                (i, Join::Hyphen)
+            } else if before
+                .into_iter()
+                .chain(after)
+                .any(|g| grapheme_width(g) == 2)
+            {
+                (i, Join::Character)
+            } else {
+                (i, Join::Break)
             }
-            r.push(word);
-            r
         })
-        .collect()
 }
 
-fn get_words_in_lines<'a>(screen_width: usize, words: &[StyledWord<'a>]) -> Vec<WordsInLine<'a>> {
-    let words = split_words_longer_than_screen_width(screen_width, words);
-    let mut words = words.as_slice();
-    let mut lines = Vec::new();
-    while words.len() > 0 {
-        let mut line = WordsInLine {
-            words: Vec::new(),
-            remaining_space: screen_width,
+// Soft hyphens are only shown as the hyphen at the end of a line, and zero width spaces
+// only mark where the line can end, so they are left out of the pieces
+const INVISIBLE: [char; 2] = [SOFT_HYPHEN, '\u{200b}'];
+const SOFT_HYPHEN: char = '\u{ad}';
+
+// Splits a fragment of a word into pieces where the line can end, which are the break
+// opportunities and the hyphenation points between them, as well as wherever it has to be
+// cut because it doesn't fit on a line. The last piece gets `last_join`.
+fn split_fragment(
+    screen_width: usize,
+    index: usize,
+    word: &StyledWord,
+    hyphenation: bool,
+    last_join: Join,
+    pieces: &mut Vec<Piece>,
+) {
+    let mut breaks: Vec<(usize, Join)> = if word.style.code {
+        Vec::new()
+    } else {
+        break_opportunities(word.text).collect()
+    };
+    breaks.push((word.text.len(), last_join));
+    if !word.style.code {
+        // Parts of the word that don't fit on a line are always hyphenated
+        let mut segment_start = 0;
+        for (segment_end, join) in std::mem::take(&mut breaks) {
+            let segment = word.text[segment_start..segment_end].trim_end_matches(INVISIBLE);
+            if hyphenation || text_width(segment) > screen_width {
+                breaks.extend(
+                    hyphenation_points(segment)
+                        .into_iter()
+                        .map(|point| (segment_start + point, Join::Hyphen)),
+                );
+            }
+            breaks.push((segment_end, join));
+            segment_start = segment_end;
+        }
+    }
+
+    let mut start = 0;
+    for (end, join) in breaks {
+        let max_width = match join {
+            Join::Hyphen => screen_width.saturating_sub(1).max(1),
+            _ => screen_width,
         };
+        let visible_end = start + word.text[start..end].trim_end_matches(INVISIBLE).len();
+        if visible_end == start {
+            // Whatever was glued to the invisible text can end the line instead
+            if let Some(previous) = pieces.last_mut().filter(|piece| piece.join != Join::Space) {
+                previous.join = join;
+            }
+        }
+        while start < visible_end {
+            let (cut, rest) = split_at_width(&word.text[start..visible_end], max_width);
+            let width = text_width(cut);
+            let join = match join {
+                _ if !rest.is_empty() => Join::Cut,
+                // Not even a single character fits next to the hyphen
+                Join::Hyphen if width + 1 > screen_width => Join::Cut,
+                join => join,
+            };
+            pieces.push(Piece {
+                fragment: index,
+                range: start..start + cut.len(),
+                width,
+                join,
+            });
+            start += cut.len();
+        }
+        start = end;
+    }
+}
 
-        loop {
-            line.words.push(words[0]);
-            line.remaining_space -= styled_word_length(&words[0]);
-            words = &words[1..];
-            let is_last_word_in_line =
-                words.len() == 0 || line.remaining_space <= styled_word_length(&words[0]);
-            // For every word except the last one, we need to subtract 1 from the remaining space
-            // to account for the whitespace. (There must be at least one space after each word.)
-            if is_last_word_in_line {
-                break;
+// Returns the width of a line made of the pieces, including the spaces between the words
+// and the hyphen at the end
+fn measure_line(pieces: &[Piece]) -> usize {
+    let (last, rest) = pieces.split_last().unwrap();
+    let spaces = rest
+        .iter()
+        .filter(|piece| piece.join == Join::Space)
+        .count();
+    let hyphen = if last.join == Join::Hyphen { 1 } else { 0 };
+    let words_width: usize = pieces.iter().map(|piece| piece.width).sum();
+    words_width + spaces + hyphen
+}
+
+fn words_in_line<'a>(
+    screen_width: usize,
+    words: &[StyledWord<'a>],
+    pieces: &[Piece],
+) -> WordsInLine<'a> {
+    let mut line_words: Vec<Vec<StyledWord<'a>>> = Vec::new();
+    let mut gaps = Vec::new();
+    let mut part_start = 0;
+    for (i, piece) in pieces.iter().enumerate() {
+        // Pieces of the same fragment are put back together, up to the end of the word, or
+        // to where invisible text was left out between them
+        let part_ends = pieces.get(i + 1).is_none_or(|next| {
+            next.fragment != piece.fragment
+                || next.range.start != piece.range.end
+                || matches!(piece.join, Join::Space | Join::Character)
+        });
+        if !part_ends {
+            continue;
+        }
+        let fragment = words[piece.fragment];
+        let start = pieces[part_start].range.start;
+        let part = StyledWord {
+            text: &fragment.text[start..piece.range.end],
+            ..fragment
+        };
+        match part_start.checked_sub(1).map(|j| pieces[j].join) {
+            Some(Join::Space) => {
+                gaps.push(1);
+                line_words.push(vec![part]);
+            }
+            Some(Join::Character) => {
+                gaps.push(0);
+                line_words.push(vec![part]);
             }
-            line.remaining_space -= 1;
+            Some(_) => line_words.last_mut().unwrap().push(part),
+            None => line_words.push(vec![part]),
         }
+        part_start = i + 1;
+    }
+    WordsInLine {
+        words: line_words,
+        gaps,
+        // A wide character on a screen that's one cell wide doesn't fit at all
+        remaining_space: screen_width.saturating_sub(measure_line(pieces)),
+        hyphenated: pieces.last().unwrap().join == Join::Hyphen,
+    }
+}
 
-        lines.push(line);
+// Whether the line can't end before the piece at `end`
+fn is_glued(pieces: &[Piece], end: usize) -> bool {
+    end < pieces.len() && pieces[end - 1].join == Join::Glue
+}
+
+fn get_words_in_lines<'a>(
+    screen_width: usize,
+    words: &[StyledWord<'a>],
+    hyphenation: bool,
+) -> Vec<WordsInLine<'a>> {
+    let pieces = split_into_pieces(screen_width, words, hyphenation);
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < pieces.len() {
+        // Every line gets at least one piece, even if it doesn't fit
+        let mut end = start + 1;
+        while end < pieces.len() && measure_line(&pieces[start..=end]) <= screen_width {
+            end += 1;
+        }
+        // Back off to the start of a word that's glued together from fragments, or take
+        // all of it if it's the only word on the line
+        if let Some(glued) = (start + 1..=end).rev().find(|&e| !is_glued(&pieces, e)) {
+            end = glued;
+        } else {
+            while is_glued(&pieces, end) {
+                end += 1;
+            }
+        }
+        lines.push(words_in_line(screen_width, words, &pieces[start..end]));
+        start = end;
     }
     lines
 }
 
-pub fn calculate_layout<'a>(screen_width: usize, words: &[StyledWord<'a>]) -> Vec<LayoutLine<'a>> {
-    let mut layout_lines = Vec::new();
-    let words_in_lines = get_words_in_lines(screen_width, words);
-    for (i, words_in_line) in words_in_lines.iter().enumerate() {
-        if i != words_in_lines.len() - 1 {
-            layout_lines.push(words_in_line.spread_evenly());
-        } else {
-            layout_lines.push(words_in_line.align_left());
+// How bad a line looks, the way TeX measures it. Stretching every gap to twice its width
+// has a badness of 100, and lines that need more than that get much worse quickly. The
+// line penalty keeps the paragraph from using more lines than it needs.
+fn line_demerits(stretch: usize, gaps: usize, is_last_line: bool, hyphenated: bool) -> f64 {
+    const LINE_PENALTY: f64 = 10.0;
+    const HYPHEN_PENALTY: f64 = 50.0;
+    const MAX_BADNESS: f64 = 10000.0;
+    let badness = if is_last_line || stretch == 0 {
+        0.0
+    } else if gaps == 0 {
+        MAX_BADNESS
+    } else {
+        (100.0 * (stretch as f64 / gaps as f64).powi(3)).min(MAX_BADNESS)
+    };
+    let penalty = if hyphenated { HYPHEN_PENALTY } else { 0.0 };
+    (LINE_PENALTY + badness).powi(2) + penalty.powi(2)
+}
+
+fn get_optimal_words_in_lines<'a>(
+    screen_width: usize,
+    words: &[StyledWord<'a>],
+    hyphenation: bool,
+) -> Vec<WordsInLine<'a>> {
+    let pieces = split_into_pieces(screen_width, words, hyphenation);
+
+    // For every piece, the lowest total demerits of breaking the paragraph right before it,
+    // and where the line ending there starts
+    let mut best = vec![(f64::INFINITY, 0); pieces.len() + 1];
+    best[0] = (0.0, 0);
+    for end in 1..=pieces.len() {
+        if is_glued(&pieces, end) {
+            continue;
+        }
+        let hyphenated = pieces[end - 1].join == Join::Hyphen;
+        let mut line_width = if hyphenated { 1 } else { 0 };
+        let mut gaps = 0;
+        for start in (0..end).rev() {
+            line_width += pieces[start].width;
+            if start != end - 1 {
+                match pieces[start].join {
+                    Join::Space => {
+                        line_width += 1;
+                        gaps += 1;
+                    }
+                    Join::Character => gaps += 1,
+                    _ => {}
+                }
+            }
+            if line_width > screen_width && start != end - 1 {
+                break;
+            }
+            let stretch = screen_width.saturating_sub(line_width);
+            let is_last_line = end == pieces.len();
+            let demerits = best[start].0 + line_demerits(stretch, gaps, is_last_line, hyphenated);
+            if demerits < best[end].0 {
+                best[end] = (demerits, start);
+            }
         }
     }
-    layout_lines
+
+    let mut lines = Vec::new();
+    let mut end = pieces.len();
+    while end > 0 {
+        let start = best[end].1;
+        lines.push(words_in_line(screen_width, words, &pieces[start..end]));
+        end = start;
+    }
+    lines.reverse();
+    lines
+}
+
+fn align_lines<'a>(
+    words_in_lines: &[WordsInLine<'a>],
+    alignment: Alignment,
+) -> Vec<LayoutLine<'a>> {
+    let mut wide_gaps = Vec::new();
+    words_in_lines
+        .iter()
+        .enumerate()
+        .map(|(i, words_in_line)| match alignment {
+            Alignment::Justify if i != words_in_lines.len() - 1 => {
+                words_in_line.spread_evenly(i, &mut wide_gaps)
+            }
+            Alignment::Justify | Alignment::Left => words_in_line.align_left(),
+            Alignment::Right => words_in_line.align_right(),
+            Alignment::Center => words_in_line.align_center(),
+        })
+        .collect()
+}
+
+pub fn calculate_layout<'a>(
+    screen_width: usize,
+    words: &[StyledWord<'a>],
+    options: &LayoutOptions,
+    alignment: Alignment,
+) -> Vec<LayoutLine<'a>> {
+    // A line that ends with a hard break is laid out like the last line of a paragraph
+    words
+        .split_inclusive(|word| word.separator == Separator::LineBreak)
+        .flat_map(|words| {
+            let words_in_lines = match options.line_breaking {
+                LineBreaking::FirstFit => {
+                    get_words_in_lines(screen_width, words, options.hyphenation)
+                }
+                LineBreaking::TotalFit => {
+                    get_optimal_words_in_lines(screen_width, words, options.hyphenation)
+                }
+            };
+            align_lines(&words_in_lines, alignment)
+        })
+        .collect()
+}
+
+/// Like `calculate_layout`, but with simple first-fit line breaking and no hyphenation,
+/// for text that has to fit into narrow spaces, like table cells.
+pub fn calculate_aligned_layout<'a>(
+    screen_width: usize,
+    words: &[StyledWord<'a>],
+    alignment: ColumnAlignment,
+) -> Vec<LayoutLine<'a>> {
+    align_lines(
+        &get_words_in_lines(screen_width, words, false),
+        alignment.into(),
+    )
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::markdown_parser::Link;
 
     pub fn debug_print(layout: &[LayoutLine]) {
         for line in layout {
@@ -209,7 +703,7 @@ pub mod tests {
                     }
                 }
             }
-            print!("\n");
+            println!();
         }
     }
 
@@ -219,40 +713,419 @@ they are \"antiques\". I'm happy to still have my Borland TurboPascal/C/Asm manu
 
     #[test]
     fn layout_tests() {
+        let all_options = [LineBreaking::FirstFit, LineBreaking::TotalFit]
+            .iter()
+            .flat_map(|&line_breaking| {
+                [false, true].iter().map(move |&hyphenation| LayoutOptions {
+                    line_breaking,
+                    hyphenation,
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
         for screen_width in (20..=120).rev() {
-            let words: Vec<StyledWord> = TEXT
-                .split_ascii_whitespace()
-                .into_iter()
-                .map(|w| w.into())
-                .collect();
-            let layout = calculate_layout(screen_width, &words);
-            for line in layout.iter() {
-                let mut sum = 0;
-
-                for layout_element in line.elements.iter() {
-                    match layout_element {
-                        LayoutElement::Word(w) => sum += w.text.len(),
-                        &LayoutElement::Whitespace(n) => {
-                            sum += n;
-                            if n == 0 {
-                                debug_print(&layout);
-                                panic!(format!(
+            for options in all_options.iter() {
+                let words: Vec<StyledWord> =
+                    TEXT.split_ascii_whitespace().map(|w| w.into()).collect();
+                let layout = calculate_layout(screen_width, &words, options, Alignment::Justify);
+                for line in layout.iter() {
+                    let mut sum = 0;
+
+                    for layout_element in line.elements.iter() {
+                        match layout_element {
+                            LayoutElement::Word(w) => sum += styled_word_length(w),
+                            &LayoutElement::Whitespace(n) => {
+                                sum += n;
+                                if n == 0 {
+                                    debug_print(&layout);
+                                    panic!(
                                     "Found a 0-length whitespace, see debug output (screen width {})",
                                     screen_width
-                                ));
+                                );
+                                }
                             }
                         }
                     }
-                }
 
-                if sum != screen_width {
-                    debug_print(&layout);
-                    panic!(format!(
-                        "Line width not equal to screen width ({}), see debug output",
-                        screen_width
-                    ));
+                    if sum != screen_width {
+                        debug_print(&layout);
+                        panic!(
+                            "Line width not equal to screen width ({}), see debug output",
+                            screen_width
+                        );
+                    }
                 }
             }
         }
     }
+
+    #[test]
+    fn links_take_up_only_their_text() {
+        let word = StyledWord {
+            link: Some(Link {
+                destination: "https://example.com/a/very/long/destination",
+                title: None,
+            }),
+            ..StyledWord::from("example")
+        };
+
+        assert_eq!(styled_word_length(&word), 7);
+    }
+
+    #[test]
+    fn width_is_measured_in_cells() {
+        assert_eq!(text_width("naïve"), 5);
+        assert_eq!(text_width("e\u{301}te\u{301}"), 3);
+        assert_eq!(text_width("日本語"), 6);
+        assert_eq!(text_width("👩‍👩‍👧"), 2);
+    }
+
+    #[test]
+    fn splitting_keeps_grapheme_clusters_together() {
+        assert_eq!(
+            split_at_width("e\u{301}e\u{301}", 1),
+            ("e\u{301}", "e\u{301}")
+        );
+        assert_eq!(split_at_width("日本語", 3), ("日", "本語"));
+        assert_eq!(split_at_width("日本語", 1), ("日", "本語"));
+        assert_eq!(split_at_width("abc", 5), ("abc", ""));
+    }
+
+    #[test]
+    fn long_non_ascii_words_are_split() {
+        let words: Vec<StyledWord> = vec!["Größenordnung".into(), "日本語のテキスト".into()];
+        for screen_width in 1..=20 {
+            let layout = calculate_layout(
+                screen_width,
+                &words,
+                &LayoutOptions::default(),
+                Alignment::Justify,
+            );
+            for line in layout.iter() {
+                let width: usize = line
+                    .elements
+                    .iter()
+                    .map(|element| match element {
+                        LayoutElement::Word(word) => styled_word_length(word),
+                        &LayoutElement::Whitespace(n) => n,
+                    })
+                    .sum();
+                // A wide character can't be split, so it can stick out of a single cell
+                assert!(
+                    width == screen_width || screen_width == 1,
+                    "{}",
+                    screen_width
+                );
+            }
+        }
+    }
+
+    fn total_demerits(lines: &[WordsInLine]) -> f64 {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let gaps = line.words.len() - 1;
+                let is_last_line = i == lines.len() - 1;
+                line_demerits(line.remaining_space, gaps, is_last_line, line.hyphenated)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn total_fit_beats_first_fit() {
+        let words: Vec<StyledWord> = TEXT.split_ascii_whitespace().map(|w| w.into()).collect();
+        let mut better_somewhere = false;
+        for screen_width in 10..=120 {
+            let first_fit = total_demerits(&get_words_in_lines(screen_width, &words, false));
+            let total_fit =
+                total_demerits(&get_optimal_words_in_lines(screen_width, &words, false));
+            assert!(total_fit <= first_fit, "{}", screen_width);
+            better_somewhere |= total_fit < first_fit;
+        }
+        assert!(better_somewhere);
+    }
+
+    fn line_texts(layout: &[LayoutLine]) -> Vec<String> {
+        layout
+            .iter()
+            .map(|line| {
+                line.elements
+                    .iter()
+                    .map(|element| match element {
+                        LayoutElement::Word(word) => word.text.to_string(),
+                        &LayoutElement::Whitespace(n) => " ".repeat(n),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn long_words_are_hyphenated() {
+        let words: Vec<StyledWord> = vec!["incomprehensibilities".into()];
+        let options = LayoutOptions {
+            line_breaking: LineBreaking::FirstFit,
+            hyphenation: false,
+            ..Default::default()
+        };
+
+        let layout = calculate_layout(12, &words, &options, Alignment::Justify);
+
+        let lines = line_texts(&layout);
+        assert!(lines.len() > 1);
+        assert!(lines[..lines.len() - 1]
+            .iter()
+            .all(|line| line.trim_end().ends_with('-')));
+        let joined: String = lines
+            .iter()
+            .map(|line| line.trim_end().trim_end_matches('-'))
+            .collect();
+        assert_eq!(joined, "incomprehensibilities");
+    }
+
+    #[test]
+    fn hyphenation_fills_loose_lines() {
+        let words: Vec<StyledWord> = "a b c extraordinary".split(' ').map(|w| w.into()).collect();
+        let mut options = LayoutOptions {
+            line_breaking: LineBreaking::TotalFit,
+            hyphenation: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            line_texts(&calculate_layout(16, &words, &options, Alignment::Justify))[0],
+            "a       b      c"
+        );
+
+        options.hyphenation = true;
+        let lines = line_texts(&calculate_layout(16, &words, &options, Alignment::Justify));
+        assert!(lines[0].starts_with("a b c "), "{:?}", lines);
+        assert!(lines[0].ends_with('-'), "{:?}", lines);
+    }
+
+    #[test]
+    fn code_is_never_hyphenated() {
+        let mut word = StyledWord::from("extraordinary");
+        word.style.code = true;
+
+        let layout = calculate_layout(
+            40,
+            &["a".into(), word],
+            &LayoutOptions::default(),
+            Alignment::Justify,
+        );
+
+        assert_eq!(
+            line_texts(&layout),
+            vec!["a extraordinary".to_string() + &" ".repeat(25)]
+        );
+    }
+
+    #[test]
+    fn fragments_are_not_split_up() {
+        let mut words: Vec<StyledWord> = "aaa bb cc ,".split(' ').map(|w| w.into()).collect();
+        words[1].style.bold = true;
+        words[1].separator = Separator::Nothing;
+        words[2].style.code = true;
+        words[2].separator = Separator::Nothing;
+        let options = LayoutOptions {
+            hyphenation: false,
+            ..Default::default()
+        };
+
+        for &line_breaking in &[LineBreaking::FirstFit, LineBreaking::TotalFit] {
+            let options = LayoutOptions {
+                line_breaking,
+                ..options
+            };
+            let layout = calculate_layout(8, &words, &options, Alignment::Left);
+            assert_eq!(line_texts(&layout), vec!["aaa     ", "bbcc,   "]);
+            assert_eq!(
+                layout[1].elements[..3],
+                [
+                    LayoutElement::Word(words[1]),
+                    LayoutElement::Word(words[2]),
+                    LayoutElement::Word(words[3])
+                ]
+            );
+
+            // Fragments of words that don't fit on a line are split up like other long words
+            let layout = calculate_layout(4, &words, &options, Alignment::Left);
+            assert_eq!(line_texts(&layout), vec!["aaa ", "bbcc", ",   "]);
+        }
+    }
+
+    #[test]
+    fn lines_break_between_cjk_characters() {
+        let words: Vec<StyledWord> = vec!["日本語のテキストです。".into()];
+
+        for &line_breaking in &[LineBreaking::FirstFit, LineBreaking::TotalFit] {
+            let options = LayoutOptions {
+                line_breaking,
+                ..Default::default()
+            };
+            let layout = calculate_layout(9, &words, &options, Alignment::Justify);
+            // The gaps between the characters are stretched to fill the line, and the full
+            // stop doesn't start a line
+            assert_eq!(
+                line_texts(&layout),
+                vec!["日 本語の", "テキス ト", "です。   "]
+            );
+        }
+    }
+
+    #[test]
+    fn lines_break_after_slashes_and_hyphens() {
+        let words: Vec<StyledWord> = vec!["input/output".into(), "well-known".into()];
+        let options = LayoutOptions {
+            line_breaking: LineBreaking::FirstFit,
+            hyphenation: false,
+            ..Default::default()
+        };
+
+        let layout = calculate_layout(8, &words, &options, Alignment::Left);
+
+        assert_eq!(
+            line_texts(&layout),
+            vec!["input/  ", "output  ", "well-   ", "known   "]
+        );
+    }
+
+    #[test]
+    fn no_break_at_non_breaking_spaces() {
+        let words: Vec<StyledWord> = vec!["aaa".into(), "10\u{a0}kg".into()];
+
+        let layout = calculate_layout(6, &words, &LayoutOptions::default(), Alignment::Left);
+
+        assert_eq!(line_texts(&layout), vec!["aaa   ", "10\u{a0}kg "]);
+    }
+
+    #[test]
+    fn soft_hyphens_are_shown_only_at_breaks() {
+        let words: Vec<StyledWord> = vec!["extra\u{ad}ordinary".into()];
+        let lines = |width| {
+            line_texts(&calculate_layout(
+                width,
+                &words,
+                &LayoutOptions::default(),
+                Alignment::Left,
+            ))
+        };
+
+        assert_eq!(lines(8), vec!["extra-  ", "ordinary"]);
+        assert_eq!(lines(14), vec!["extraordinary "]);
+
+        // Entities are fragments of their own
+        let mut words: Vec<StyledWord> = vec!["ab".into(), "\u{ad}".into(), "cd".into()];
+        words[0].separator = Separator::Nothing;
+        words[1].separator = Separator::Nothing;
+        let lines = |width| {
+            line_texts(&calculate_layout(
+                width,
+                &words,
+                &LayoutOptions::default(),
+                Alignment::Left,
+            ))
+        };
+        assert_eq!(lines(3), vec!["ab-", "cd "]);
+        assert_eq!(lines(5), vec!["abcd "]);
+    }
+
+    #[test]
+    fn zero_width_spaces_are_invisible_breaks() {
+        let words: Vec<StyledWord> = vec!["foo\u{200b}bar".into()];
+        let lines = |width| {
+            line_texts(&calculate_layout(
+                width,
+                &words,
+                &LayoutOptions::default(),
+                Alignment::Left,
+            ))
+        };
+
+        assert_eq!(lines(4), vec!["foo ", "bar "]);
+        assert_eq!(lines(7), vec!["foobar "]);
+    }
+
+    #[test]
+    fn alignments() {
+        let words: Vec<StyledWord> = "aa bb cc dd".split(' ').map(|w| w.into()).collect();
+        let options = LayoutOptions::default();
+        let lines = |alignment| line_texts(&calculate_layout(9, &words, &options, alignment));
+
+        assert_eq!(lines(Alignment::Justify), vec!["aa  bb cc", "dd       "]);
+        assert_eq!(lines(Alignment::Left), vec!["aa bb cc ", "dd       "]);
+        assert_eq!(lines(Alignment::Right), vec![" aa bb cc", "       dd"]);
+        assert_eq!(lines(Alignment::Center), vec!["aa bb cc ", "   dd    "]);
+    }
+
+    #[test]
+    fn alignments_cycle() {
+        let mut alignment = Alignment::Justify;
+        for _ in 0..3 {
+            alignment = alignment.next();
+            assert_ne!(alignment, Alignment::Justify);
+        }
+        assert_eq!(alignment.next(), Alignment::Justify);
+    }
+
+    fn words_in_line<'a>(text: &'a str, remaining_space: usize) -> WordsInLine<'a> {
+        let words: Vec<Vec<StyledWord>> = text.split(' ').map(|w| vec![w.into()]).collect();
+        WordsInLine {
+            gaps: vec![1; words.len() - 1],
+            words,
+            remaining_space,
+            hyphenated: false,
+        }
+    }
+
+    #[test]
+    fn extra_spaces_go_after_punctuation() {
+        let line = words_in_line("one two, three four", 1);
+
+        let layout = line.spread_evenly(0, &mut Vec::new());
+
+        assert_eq!(line_texts(&[layout]), vec!["one two,  three four"]);
+    }
+
+    #[test]
+    fn wide_gaps_avoid_the_line_above() {
+        let line = words_in_line("aa bb cc dd", 1);
+        let mut wide_gaps = Vec::new();
+
+        let first = line.spread_evenly(0, &mut wide_gaps);
+        let second = line.spread_evenly(2, &mut wide_gaps);
+        let third = line.spread_evenly(4, &mut wide_gaps);
+
+        assert_eq!(
+            line_texts(&[first, second, third]),
+            vec!["aa  bb cc dd", "aa bb  cc dd", "aa  bb cc dd"]
+        );
+    }
+
+    #[test]
+    fn extra_spaces_alternate_sides() {
+        let line = words_in_line("aa bb cc dd", 1);
+
+        let even = line.spread_evenly(0, &mut Vec::new());
+        let odd = line.spread_evenly(1, &mut Vec::new());
+
+        assert_eq!(
+            line_texts(&[even, odd]),
+            vec!["aa  bb cc dd", "aa bb cc  dd"]
+        );
+    }
+
+    #[test]
+    fn hard_breaks_end_lines() {
+        let mut words: Vec<StyledWord> = "aa bb cc dd ee".split(' ').map(|w| w.into()).collect();
+        words[1].separator = Separator::LineBreak;
+
+        let layout = calculate_layout(20, &words, &LayoutOptions::default(), Alignment::Justify);
+
+        assert_eq!(
+            line_texts(&layout),
+            vec!["aa bb               ", "cc dd ee            "]
+        );
+    }
 }
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     error::Error,
     fmt::{self, Display, Formatter},
 };
@@ -6,22 +7,143 @@ use std::{
 use fmt::Debug;
 
 use crate::{
-    markdown_parser::{Heading, Markdown, MarkdownElement, Style, StyledWord},
-    terminal::{Style as TerminalStyle, TerminalCanvas, TerminalError},
+    common::{Dimensions, Position},
+    markdown_parser::{
+        CodeBlock, Heading, HeadingSize, List, ListKind, Markdown, MarkdownElement, Style,
+        StyledWord,
+    },
+    terminal::{AnsiColor, Style as TerminalStyle, TerminalCanvas, TerminalError, TerminalResult},
 };
 
-use layout::LayoutElement;
+use layout::{split_at_width, text_width, LayoutElement, LayoutLine};
 
+pub use layout::{Alignment, LayoutOptions};
+
+mod hyphenation;
 mod layout;
+mod table_layout;
+
+/// A line of the rendered document, as wide as the screen.
+#[derive(Default)]
+pub struct RendererLine<'a> {
+    spans: Vec<Span<'a>>,
+    anchor: Anchor,
+}
 
-pub enum RendererLine<'a> {
-    Heading(Heading<'a>),
-    Text(&'a [StyledWord<'a>]),
+/// Where a line starts in the document, which stays the same whatever the width of the
+/// screen is: the index of the block of text, like a paragraph or a code block, and the
+/// number of letters and digits in the block before the line. Layout never adds or removes
+/// those, unlike spaces and hyphens.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Anchor {
+    block: usize,
+    position: usize,
 }
 
-struct Renderer<'a> {
+// Text in a single style
+struct Span<'a> {
+    text: Cow<'a, str>,
+    style: TerminalStyle,
+    link: Option<&'a str>,
+}
+
+impl<'a> RendererLine<'a> {
+    fn push(&mut self, text: impl Into<Cow<'a, str>>, style: TerminalStyle) {
+        self.spans.push(Span {
+            text: text.into(),
+            style,
+            link: None,
+        });
+    }
+
+    fn push_whitespace(&mut self, n: usize, style: TerminalStyle) {
+        if n > 0 {
+            self.push(" ".repeat(n), style);
+        }
+    }
+
+    fn push_word(&mut self, word: &StyledWord<'a>, base_style: &TerminalStyle, theme: &Theme) {
+        self.spans.push(Span {
+            text: word.text.into(),
+            style: to_terminal_style(word, base_style, theme),
+            link: word.link.map(|link| link.destination),
+        });
+    }
+
+    // Spaces between the words of a link, which are underlined and can be clicked like the
+    // words are
+    fn push_link_whitespace(
+        &mut self,
+        n: usize,
+        link_word: &StyledWord<'a>,
+        base_style: &TerminalStyle,
+        theme: &Theme,
+    ) {
+        let space = StyledWord {
+            style: Style::default(),
+            ..*link_word
+        };
+        self.spans.push(Span {
+            text: " ".repeat(n).into(),
+            style: to_terminal_style(&space, base_style, theme),
+            link: link_word.link.map(|link| link.destination),
+        });
+    }
+
+    // Removes the spaces at the end that can't be seen, which only pad the line to the width
+    // of the screen
+    fn trim_end(&mut self, backgrounds: bool) {
+        while let Some(span) = self.spans.last() {
+            let visible = backgrounds && span.style.background.is_some();
+            if visible || span.text.chars().any(|c| c != ' ') {
+                break;
+            }
+            self.spans.pop();
+        }
+    }
+
+    fn print(&self, terminal: &mut TerminalCanvas) -> TerminalResult<()> {
+        for span in self.spans.iter() {
+            terminal.set_style(&span.style)?;
+            terminal.set_hyperlink(span.link)?;
+            terminal.print_str(&span.text)?;
+        }
+        terminal.set_hyperlink(None)?;
+        terminal.set_style(&TerminalStyle::default())
+    }
+}
+
+pub struct Renderer<'a> {
     canvas: TerminalCanvas,
+    theme: Theme,
+    options: LayoutOptions,
+    markdown: Option<&'a Markdown<'a>>,
     lines: Vec<RendererLine<'a>>,
+    // The index of the line at the top of the screen
+    top: usize,
+    dimensions: Dimensions,
+    // The text is laid out as if the screen were at most this wide
+    max_screen_width: Option<usize>,
+}
+
+pub struct Theme {
+    pub code_foreground: Option<AnsiColor>,
+    pub code_background: Option<AnsiColor>,
+    pub code_language: Option<AnsiColor>,
+    pub quote_bar: Option<AnsiColor>,
+    pub link: Option<AnsiColor>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            code_foreground: Some(AnsiColor(223)),
+            code_background: Some(AnsiColor(236)),
+            code_language: Some(AnsiColor(245)),
+            quote_bar: Some(AnsiColor(242)),
+            link: Some(AnsiColor(39)),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -29,44 +151,388 @@ pub enum RendererError {
     TerminalError(TerminalError),
 }
 
-// XXX Temporary solution
-pub fn render(terminal: &mut TerminalCanvas, markdown: &Markdown) {
-    for element in markdown.elements.iter() {
-        match element {
-            MarkdownElement::Heading(_) => panic!(),
-            MarkdownElement::Paragraph(words) => {
-                let layout = layout::calculate_layout(terminal.width().unwrap(), words);
-                for line in layout {
-                    for layout_element in line.elements {
-                        match layout_element {
-                            LayoutElement::Word(word) => render_word(terminal, &word),
-                            LayoutElement::Whitespace(n) => render_whitespace(terminal, n),
+/// Prints the whole document at once, without scrolling, for output that doesn't go to a
+/// screen, like a pipe or a file.
+pub fn render(
+    terminal: &mut TerminalCanvas,
+    markdown: &Markdown,
+    theme: &Theme,
+    options: &LayoutOptions,
+    screen_width: usize,
+) -> RendererResult<()> {
+    let backgrounds = terminal.shows_backgrounds();
+    for mut line in layout_markdown(screen_width, markdown, theme, options) {
+        line.trim_end(backgrounds);
+        line.print(terminal)?;
+        terminal.print_str("\n")?;
+    }
+    terminal.flush()?;
+    Ok(())
+}
+
+fn layout_markdown<'a>(
+    screen_width: usize,
+    markdown: &Markdown<'a>,
+    theme: &Theme,
+    options: &LayoutOptions,
+) -> Vec<RendererLine<'a>> {
+    let (left_padding, width) = reading_column(screen_width, options.max_width);
+    let mut printer = BlockPrinter {
+        lines: Vec::new(),
+        width,
+        left_padding,
+        right_padding: screen_width - left_padding - width,
+        theme,
+        options,
+        margins: Vec::new(),
+        list_depth: 0,
+        anchor: Anchor::default(),
+    };
+    printer.print_elements(&markdown.elements, true);
+    printer.lines
+}
+
+// The first of the lines that start at `anchor`, or else the line that `anchor` is in
+fn line_at(lines: &[RendererLine], anchor: Anchor) -> usize {
+    let i = lines.partition_point(|line| line.anchor < anchor);
+    match lines.get(i) {
+        Some(line) if line.anchor == anchor => i,
+        _ => i.saturating_sub(1),
+    }
+}
+
+/// Returns where the text column starts and how wide it is. Lines that are too long are
+/// hard to read, so on wide screens the text is kept narrower and centered.
+fn reading_column(screen_width: usize, max_width: Option<usize>) -> (usize, usize) {
+    match max_width {
+        Some(max_width) if max_width < screen_width => {
+            ((screen_width - max_width) / 2, max_width.max(1))
+        }
+        _ => (0, screen_width),
+    }
+}
+
+/// Text printed to the left of each line of a nested block, such as a list bullet.
+struct Margin {
+    first_line: String,
+    other_lines: String,
+    style: TerminalStyle,
+    first_line_pending: bool,
+}
+
+impl Margin {
+    fn new(first_line: String, other_lines: String, style: TerminalStyle) -> Self {
+        Self {
+            first_line,
+            other_lines,
+            style,
+            first_line_pending: true,
+        }
+    }
+
+    fn width(&self) -> usize {
+        text_width(&self.other_lines)
+    }
+}
+
+struct BlockPrinter<'t, 'a> {
+    lines: Vec<RendererLine<'a>>,
+    width: usize,
+    // Empty space around the text column
+    left_padding: usize,
+    right_padding: usize,
+    theme: &'t Theme,
+    options: &'t LayoutOptions,
+    margins: Vec<Margin>,
+    list_depth: usize,
+    // Where the text that's printed next is
+    anchor: Anchor,
+}
+
+impl<'a> BlockPrinter<'_, 'a> {
+    fn content_width(&self) -> usize {
+        let margins_width: usize = self.margins.iter().map(Margin::width).sum();
+        self.width.saturating_sub(margins_width).max(1)
+    }
+
+    // The line that's being printed
+    fn line(&mut self) -> &mut RendererLine<'a> {
+        self.lines.last_mut().unwrap()
+    }
+
+    fn start_block(&mut self) {
+        self.anchor = Anchor {
+            block: self.anchor.block + 1,
+            position: 0,
+        };
+    }
+
+    fn advance(&mut self, text: &str) {
+        self.anchor.position += text.chars().filter(|c| c.is_alphanumeric()).count();
+    }
+
+    fn start_line(&mut self) {
+        let mut line = RendererLine {
+            anchor: self.anchor,
+            ..Default::default()
+        };
+        line.push_whitespace(self.left_padding, TerminalStyle::default());
+        for margin in self.margins.iter_mut() {
+            let text = if margin.first_line_pending {
+                &margin.first_line
+            } else {
+                &margin.other_lines
+            };
+            line.push(text.clone(), margin.style);
+            margin.first_line_pending = false;
+        }
+        self.lines.push(line);
+    }
+
+    fn end_line(&mut self) {
+        let right_padding = self.right_padding;
+        self.line()
+            .push_whitespace(right_padding, TerminalStyle::default());
+    }
+
+    fn print_blank_line(&mut self) {
+        self.start_line();
+        let width = self.content_width();
+        self.line().push_whitespace(width, TerminalStyle::default());
+        self.end_line();
+    }
+
+    fn print_elements(&mut self, elements: &[MarkdownElement<'a>], separate_blocks: bool) {
+        for (i, element) in elements.iter().enumerate() {
+            if i != 0 && separate_blocks {
+                self.print_blank_line();
+            }
+            match element {
+                MarkdownElement::Heading(heading) => self.print_heading(heading),
+                MarkdownElement::Paragraph(words) => {
+                    self.start_block();
+                    let layout = layout::calculate_layout(
+                        self.content_width(),
+                        words,
+                        self.options,
+                        self.options.alignment,
+                    );
+                    self.print_layout(&layout, &TerminalStyle::default());
+                }
+                MarkdownElement::CodeBlock(code_block) => self.print_code_block(code_block),
+                MarkdownElement::List(list) => self.print_list(list),
+                MarkdownElement::BlockQuote(elements) => self.print_block_quote(elements),
+                MarkdownElement::Table(table) => {
+                    self.start_block();
+                    let layout = table_layout::calculate_table_layout(self.content_width(), table);
+                    self.print_layout(&layout, &TerminalStyle::default());
+                }
+            }
+        }
+    }
+
+    fn print_heading(&mut self, heading: &Heading<'a>) {
+        self.start_block();
+        let style = heading_style(heading.size);
+        let width = self.content_width();
+        // Only words that don't fit on a line at all are cut in headings
+        let options = LayoutOptions {
+            hyphenation: false,
+            ..*self.options
+        };
+        let layout = layout::calculate_layout(width, &heading.words, &options, Alignment::Center);
+        self.print_layout(&layout, &style);
+        let underline = match heading.size {
+            HeadingSize::ExtraLarge => Some("═"),
+            HeadingSize::Large => Some("─"),
+            _ => None,
+        };
+        if let Some(underline) = underline {
+            self.start_line();
+            self.line().push(underline.repeat(width), style);
+            self.end_line();
+        }
+    }
+
+    fn print_code_block(&mut self, code_block: &CodeBlock<'a>) {
+        const PADDING: usize = 1;
+        let style = TerminalStyle {
+            foregound: self.theme.code_foreground,
+            background: self.theme.code_background,
+            ..Default::default()
+        };
+        self.start_block();
+        let width = self.content_width();
+        let line_width = width.saturating_sub(2 * PADDING).max(1);
+        // The language from the info string, like `rust` in "```rust", labels the top of the
+        // box on the right
+        if let Some(language) = code_block.info.split_whitespace().next() {
+            let language_style = TerminalStyle {
+                foregound: self.theme.code_language,
+                ..style
+            };
+            let (label, _) = split_at_width(language, line_width);
+            self.start_line();
+            let screen_line = self.line();
+            screen_line.push_whitespace(width.saturating_sub(PADDING + text_width(label)), style);
+            screen_line.push(label, language_style);
+            screen_line.push_whitespace(PADDING, style);
+            self.end_line();
+        }
+        for line in code_block.lines.iter() {
+            // Code is never justified, lines that don't fit are simply wrapped
+            let line = expand_tabs(line);
+            let mut rest = line.as_str();
+            loop {
+                let (chunk, remainder) = split_at_width(rest, line_width);
+                rest = remainder;
+                self.start_line();
+                let screen_line = self.line();
+                screen_line.push_whitespace(PADDING, style);
+                screen_line.push(chunk.to_string(), style);
+                screen_line
+                    .push_whitespace(width.saturating_sub(PADDING + text_width(chunk)), style);
+                self.end_line();
+                self.advance(chunk);
+                if rest.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn print_block_quote(&mut self, elements: &[MarkdownElement<'a>]) {
+        let bar_style = TerminalStyle {
+            foregound: self.theme.quote_bar,
+            ..Default::default()
+        };
+        self.margins
+            .push(Margin::new("│ ".to_string(), "│ ".to_string(), bar_style));
+        if elements.is_empty() {
+            self.print_blank_line();
+        } else {
+            self.print_elements(elements, true);
+        }
+        self.margins.pop();
+    }
+
+    fn print_list(&mut self, list: &List<'a>) {
+        const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+        let marker_width = match list.kind {
+            ListKind::Unordered => 1,
+            ListKind::Ordered { start } => {
+                // The widest number, plus one for the dot
+                (start + list.items.len() as u64 - 1).to_string().len() + 1
+            }
+        };
+        for (i, item) in list.items.iter().enumerate() {
+            if i != 0 && list.loose {
+                self.print_blank_line();
+            }
+            let marker = match list.kind {
+                ListKind::Unordered => BULLETS[self.list_depth % BULLETS.len()].to_string(),
+                ListKind::Ordered { start } => format!("{}.", start + i as u64),
+            };
+            // Item text wraps with a hanging indent, aligned after the marker
+            self.margins.push(Margin::new(
+                format!("{:>width$} ", marker, width = marker_width),
+                " ".repeat(marker_width + 1),
+                TerminalStyle::default(),
+            ));
+            self.list_depth += 1;
+            if item.is_empty() {
+                self.print_blank_line();
+            } else {
+                self.print_elements(item, list.loose);
+            }
+            self.list_depth -= 1;
+            self.margins.pop();
+        }
+    }
+
+    fn print_layout(&mut self, layout: &[LayoutLine<'a>], base_style: &TerminalStyle) {
+        let theme = self.theme;
+        for layout_line in layout {
+            self.start_line();
+            let elements = &layout_line.elements;
+            for (i, layout_element) in elements.iter().enumerate() {
+                match layout_element {
+                    LayoutElement::Word(word) => {
+                        self.line().push_word(word, base_style, theme);
+                        self.advance(word.text);
+                    }
+                    &LayoutElement::Whitespace(n) => {
+                        match (i.checked_sub(1).map(|i| &elements[i]), elements.get(i + 1)) {
+                            (
+                                Some(LayoutElement::Word(previous)),
+                                Some(LayoutElement::Word(next)),
+                            ) if previous.link.is_some() && previous.link == next.link => self
+                                .line()
+                                .push_link_whitespace(n, previous, base_style, theme),
+                            _ => self.line().push_whitespace(n, *base_style),
                         }
                     }
                 }
             }
+            self.end_line();
         }
     }
 }
 
-fn render_word(terminal: &mut TerminalCanvas, word: &StyledWord) {
-    terminal.set_style(&to_terminal_style(&word.style)).unwrap();
-    terminal.print_str(word.text).unwrap();
-}
-
-fn render_whitespace(terminal: &mut TerminalCanvas, n: usize) {
-    for _ in 0..n {
-        terminal.print_str(" ").unwrap();
+fn expand_tabs(line: &str) -> String {
+    const TAB_WIDTH: usize = 4;
+    let mut expanded = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c == '\t' {
+            let column = text_width(&expanded);
+            expanded.extend(std::iter::repeat_n(' ', TAB_WIDTH - column % TAB_WIDTH));
+        } else {
+            expanded.push(c);
+        }
     }
+    expanded
 }
 
-fn to_terminal_style(style: &Style) -> TerminalStyle {
+fn heading_style(size: HeadingSize) -> TerminalStyle {
+    let (color, italic) = match size {
+        HeadingSize::ExtraLarge => (207, false),
+        HeadingSize::Large => (171, false),
+        HeadingSize::Medium => (135, false),
+        HeadingSize::Small => (99, false),
+        HeadingSize::ExtraSmall => (99, true),
+        HeadingSize::Tiny => (245, true),
+    };
     TerminalStyle {
-        foregound: None,
+        foregound: Some(AnsiColor(color)),
         background: None,
-        bold: style.bold,
-        italic: style.italic,
+        bold: true,
+        italic,
+        underline: false,
+    }
+}
+
+fn to_terminal_style(
+    word: &StyledWord,
+    base_style: &TerminalStyle,
+    theme: &Theme,
+) -> TerminalStyle {
+    let style = &word.style;
+    let mut terminal_style = TerminalStyle {
+        foregound: base_style.foregound,
+        background: base_style.background,
+        bold: base_style.bold || style.bold,
+        italic: base_style.italic || style.italic,
+        underline: base_style.underline,
+    };
+    if style.code {
+        terminal_style.foregound = theme.code_foreground.or(terminal_style.foregound);
+        terminal_style.background = theme.code_background.or(terminal_style.background);
+    }
+    if word.link.is_some() {
+        terminal_style.foregound = theme.link.or(terminal_style.foregound);
+        terminal_style.underline = true;
     }
+    terminal_style
 }
 
 impl Display for RendererError {
@@ -88,23 +554,298 @@ impl From<TerminalError> for RendererError {
 pub type RendererResult<T> = std::result::Result<T, RendererError>;
 
 impl<'a> Renderer<'a> {
-    pub fn new(canvas: TerminalCanvas) -> Self {
-        Self {
+    pub fn new(
+        canvas: TerminalCanvas,
+        theme: Theme,
+        options: LayoutOptions,
+        max_screen_width: Option<usize>,
+    ) -> RendererResult<Self> {
+        let dimensions = canvas.dimensions()?;
+        Ok(Self {
             canvas,
+            theme,
+            options,
+            markdown: None,
             lines: Vec::new(),
+            top: 0,
+            dimensions,
+            max_screen_width,
+        })
+    }
+
+    pub fn load_markdown(&mut self, markdown: &'a Markdown<'a>) {
+        self.markdown = Some(markdown);
+        self.lines.clear();
+        self.top = 0;
+        self.relayout();
+    }
+
+    // Lays the document out again, keeping the same text at the top of the screen
+    fn relayout(&mut self) {
+        if let Some(markdown) = self.markdown {
+            let anchor = self.lines.get(self.top).map(|line| line.anchor);
+            let width = self
+                .max_screen_width
+                .map_or(self.dimensions.width, |width| {
+                    width.min(self.dimensions.width)
+                });
+            self.lines = layout_markdown(width, markdown, &self.theme, &self.options);
+            self.scroll_to(anchor.map_or(0, |anchor| line_at(&self.lines, anchor)));
         }
     }
 
-    pub fn load_markdown(&mut self, markdown: &Markdown<'a>) {
-        // XXX Convert the markdown into `self.lines`
+    /// Lays the document out for the new size of the screen.
+    pub fn resize(&mut self, dimensions: Dimensions) {
+        self.dimensions = dimensions;
+        self.relayout();
     }
 
+    /// Switches paragraphs to the next alignment.
+    pub fn cycle_alignment(&mut self) {
+        self.options.alignment = self.options.alignment.next();
+        self.relayout();
+    }
+
+    /// Switches between first-fit and total-fit line breaking.
+    pub fn toggle_line_breaking(&mut self) {
+        self.options.line_breaking = self.options.line_breaking.toggle();
+        self.relayout();
+    }
+
+    /// Paints the lines that fit on the screen, starting with the top one.
     pub fn paint(&mut self) -> RendererResult<()> {
         self.canvas.clear()?;
+        let height = self.canvas.height()?;
+        let visible_lines = self.lines.iter().skip(self.top).take(height);
+        for (y, line) in visible_lines.enumerate() {
+            self.canvas.move_to(&Position { x: 0, y: y as u32 })?;
+            line.print(&mut self.canvas)?;
+        }
+        self.canvas.flush()?;
         Ok(())
     }
 
-    pub fn scroll_down(&mut self) {}
+    /// The number of lines that fit on the screen.
+    pub fn height(&self) -> usize {
+        self.dimensions.height
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll_to(self.top.saturating_add(lines));
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll_to(self.top.saturating_sub(lines));
+    }
+
+    /// Puts the line `top` at the top of the screen, but never scrolls past the point where
+    /// the last line of the document is at the bottom.
+    pub fn scroll_to(&mut self, top: usize) {
+        let last_top = self.lines.len().saturating_sub(self.dimensions.height);
+        self.top = top.min(last_top);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::terminal;
+
+    fn line_width(line: &RendererLine) -> usize {
+        line.spans.iter().map(|span| text_width(&span.text)).sum()
+    }
+
+    const DOCUMENT: &str = "# Heading\n\nSome *text* with a [link](https://example.com) in it.\n\n\
+                            > A quote\n\n1. One\n2. Two\n\n```\ncode\tblock\n```\n\n| a | b |\n|---|---|\n| c | d |\n";
+
+    #[test]
+    fn lines_fill_the_screen() {
+        let markdown = Markdown::parse(DOCUMENT).unwrap();
+        for &screen_width in &[20, 80, 120] {
+            let lines = layout_markdown(
+                screen_width,
+                &markdown,
+                &Theme::default(),
+                &LayoutOptions::default(),
+            );
+            assert!(lines.len() > 10);
+            for line in lines.iter() {
+                assert_eq!(line_width(line), screen_width);
+            }
+        }
+    }
+
+    #[test]
+    fn links_include_the_spaces_between_their_words() {
+        let markdown = Markdown::parse("A [long link](https://example.com) here").unwrap();
+        let lines = layout_markdown(40, &markdown, &Theme::default(), &LayoutOptions::default());
+
+        let linked: Vec<&str> = lines[0]
+            .spans
+            .iter()
+            .filter(|span| span.link == Some("https://example.com"))
+            .map(|span| span.text.as_ref())
+            .collect();
+        assert_eq!(linked, vec!["long", " ", "link"]);
+        assert!(lines[0]
+            .spans
+            .iter()
+            .all(|span| span.style.underline == span.link.is_some()));
+    }
+
+    #[test]
+    fn code_blocks_are_labeled_with_their_language() {
+        let markdown =
+            Markdown::parse("```rust ignore\nfn main() {}\n```\n\n```\ntext\n```").unwrap();
+        let lines = layout_markdown(30, &markdown, &Theme::default(), &LayoutOptions::default());
+
+        let texts: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(
+            texts,
+            vec![
+                format!("{:>29} ", "rust"),
+                format!(" {:<29}", "fn main() {}"),
+                " ".repeat(30),
+                format!(" {:<29}", "text"),
+            ]
+        );
+        let label = &lines[0].spans[1];
+        assert_eq!(label.style.foregound, Theme::default().code_language);
+    }
+
+    #[test]
+    fn headings_are_not_hyphenated() {
+        let text =
+            "### An introduction to the configuration of terminals\n\n### Supercalifragilistic";
+        let markdown = Markdown::parse(text).unwrap();
+        let lines = layout_markdown(16, &markdown, &Theme::default(), &LayoutOptions::default());
+
+        let texts: Vec<String> = lines
+            .iter()
+            .map(|line| line_text(line).trim().to_string())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "An introduction",
+                "to the",
+                "configuration of",
+                "terminals",
+                "",
+                "Supercalifrag-",
+                "ilistic"
+            ]
+        );
+    }
+
+    #[test]
+    fn only_padding_is_trimmed() {
+        let markdown = Markdown::parse("Some text\n\n```\ncode\n```").unwrap();
+        let mut lines =
+            layout_markdown(40, &markdown, &Theme::default(), &LayoutOptions::default());
+        let widths = |lines: &mut Vec<RendererLine>, backgrounds| {
+            for line in lines.iter_mut() {
+                line.trim_end(backgrounds);
+            }
+            lines.iter().map(line_width).collect::<Vec<_>>()
+        };
+
+        // The background of the code block goes all the way to the end of the line
+        assert_eq!(widths(&mut lines, true), vec![9, 0, 40]);
+        assert_eq!(widths(&mut lines, false), vec![9, 0, 5]);
+    }
+
+    fn line_text(line: &RendererLine) -> String {
+        line.spans.iter().map(|span| span.text.as_ref()).collect()
+    }
+
+    fn renderer<'a>(width: usize, height: usize) -> Renderer<'a> {
+        let (canvas, _) = terminal::start().unwrap();
+        Renderer {
+            canvas,
+            theme: Theme::default(),
+            options: LayoutOptions::default(),
+            markdown: None,
+            lines: Vec::new(),
+            top: 0,
+            dimensions: Dimensions { width, height },
+            max_screen_width: None,
+        }
+    }
+
+    #[test]
+    fn scrolling_stops_at_the_ends() {
+        let markdown = Markdown::parse(DOCUMENT).unwrap();
+        let mut renderer = renderer(40, 5);
+        renderer.load_markdown(&markdown);
+        let last_top = renderer.lines.len() - 5;
+
+        renderer.scroll_up(1);
+        assert_eq!(renderer.top, 0);
+        renderer.scroll_down(3);
+        assert_eq!(renderer.top, 3);
+        renderer.scroll_down(usize::MAX);
+        assert_eq!(renderer.top, last_top);
+        renderer.scroll_up(2);
+        assert_eq!(renderer.top, last_top - 2);
+
+        // A document that fits on the screen doesn't scroll at all
+        renderer.dimensions.height = 100;
+        renderer.scroll_down(1);
+        assert_eq!(renderer.top, 0);
+    }
+
+    #[test]
+    fn screen_width_can_be_capped() {
+        let markdown = Markdown::parse(DOCUMENT).unwrap();
+        let mut renderer = renderer(100, 5);
+        renderer.max_screen_width = Some(50);
+        renderer.load_markdown(&markdown);
+        assert!(renderer
+            .lines
+            .iter()
+            .all(|line| line_text(line).chars().count() == 50));
+
+        renderer.resize(Dimensions {
+            width: 30,
+            height: 5,
+        });
+        assert!(renderer
+            .lines
+            .iter()
+            .all(|line| line_text(line).chars().count() == 30));
+    }
+
+    #[test]
+    fn resizing_keeps_the_text_at_the_top() {
+        let words: Vec<String> = (1..=80).map(|i| format!("word{}.", i)).collect();
+        let document = format!("# Heading\n\n{}\n\n```\ncode\n```", words.join(" "));
+        let markdown = Markdown::parse(&document).unwrap();
+        let mut renderer = renderer(40, 5);
+        renderer.load_markdown(&markdown);
 
-    pub fn scroll_up(&mut self) {}
+        renderer.scroll_down(6);
+        let top_text = line_text(&renderer.lines[renderer.top]);
+        let first_word = top_text.split_whitespace().next().unwrap().to_string();
+        for &width in &[25, 60, 40] {
+            renderer.resize(Dimensions { width, height: 5 });
+            assert!(line_text(&renderer.lines[renderer.top]).contains(&first_word));
+        }
+
+        renderer.scroll_to(0);
+        renderer.resize(Dimensions {
+            width: 30,
+            height: 5,
+        });
+        assert_eq!(renderer.top, 0);
+    }
+
+    #[test]
+    fn reading_column_is_centered() {
+        assert_eq!(reading_column(240, Some(80)), (80, 80));
+        assert_eq!(reading_column(101, Some(80)), (10, 80));
+        assert_eq!(reading_column(60, Some(80)), (0, 60));
+        assert_eq!(reading_column(240, None), (0, 240));
+    }
 }
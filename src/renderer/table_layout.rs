@@ -0,0 +1,297 @@
+use crate::markdown_parser::{ColumnAlignment, StyledWord, Table, TableCell};
+
+use super::layout::{self, word_widths, LayoutElement, LayoutLine};
+
+// Each column has a space of padding on both sides, and a border on the right.
+// The table also has one more border on the left.
+const COLUMN_DECORATION_WIDTH: usize = 3;
+
+fn border(text: &'static str) -> LayoutElement<'static> {
+    LayoutElement::Word(text.into())
+}
+
+fn cell_min_width(cell: &[StyledWord]) -> usize {
+    word_widths(cell).into_iter().max().unwrap_or(0)
+}
+
+fn cell_max_width(cell: &[StyledWord]) -> usize {
+    let widths = word_widths(cell);
+    let words_width: usize = widths.iter().sum();
+    words_width + widths.len().saturating_sub(1)
+}
+
+fn column_extents(table: &Table, extent: impl Fn(&[StyledWord]) -> usize) -> Vec<usize> {
+    (0..table.alignments.len())
+        .map(|column| {
+            std::iter::once(&table.header)
+                .chain(table.rows.iter())
+                .map(|row| extent(&row[column]))
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect()
+}
+
+/// Distributes `available` columns of the screen between the table columns. Columns get
+/// their widest content if everything fits. Otherwise, every column gets at least its
+/// longest word, and the rest is split proportionally to how much each column would still
+/// need. If not even the longest words fit, columns are shrunk proportionally and their
+/// words get broken up.
+fn distribute_widths(available: usize, min: &[usize], max: &[usize]) -> Vec<usize> {
+    let min_total: usize = min.iter().sum();
+    let max_total: usize = max.iter().sum();
+    if max_total <= available {
+        return max.to_vec();
+    }
+
+    if min_total > available {
+        let mut widths: Vec<usize> = min
+            .iter()
+            .map(|&width| (width * available / min_total).max(1))
+            .collect();
+        // Rounding up to 1 might have taken more than what's available
+        while widths.iter().sum::<usize>() > available {
+            let widest = (0..widths.len()).max_by_key(|&i| widths[i]).unwrap();
+            widths[widest] -= 1;
+        }
+        return widths;
+    }
+
+    let extra = available - min_total;
+    let wanted = max_total - min_total;
+    let mut widths: Vec<usize> = min
+        .iter()
+        .zip(max.iter())
+        .map(|(&min, &max)| min + (max - min) * extra / wanted)
+        .collect();
+    // Hand out whatever got lost to rounding
+    let mut leftover = available - widths.iter().sum::<usize>();
+    for (width, &max) in widths.iter_mut().zip(max.iter()) {
+        if leftover == 0 {
+            break;
+        }
+        if *width < max {
+            *width += 1;
+            leftover -= 1;
+        }
+    }
+    widths
+}
+
+fn rule<'a>(
+    widths: &[usize],
+    left: &'static str,
+    middle: &'static str,
+    right: &'static str,
+) -> Vec<LayoutElement<'a>> {
+    let mut elements = vec![border(left)];
+    for (i, &width) in widths.iter().enumerate() {
+        for _ in 0..width + 2 {
+            elements.push(border("─"));
+        }
+        elements.push(border(if i == widths.len() - 1 { right } else { middle }));
+    }
+    elements
+}
+
+fn emphasize<'a>(cell: &[StyledWord<'a>]) -> Vec<StyledWord<'a>> {
+    cell.iter()
+        .map(|word| {
+            let mut word = *word;
+            word.style.bold = true;
+            word
+        })
+        .collect()
+}
+
+fn row_lines<'a>(
+    row: &[TableCell<'a>],
+    widths: &[usize],
+    alignments: &[ColumnAlignment],
+) -> Vec<Vec<LayoutElement<'a>>> {
+    let cells: Vec<Vec<LayoutLine<'a>>> = row
+        .iter()
+        .zip(widths.iter().zip(alignments.iter()))
+        .map(|(cell, (&width, &alignment))| {
+            layout::calculate_aligned_layout(width, cell, alignment)
+        })
+        .collect();
+    let height = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+    let mut cells: Vec<_> = cells.into_iter().map(Vec::into_iter).collect();
+    (0..height)
+        .map(|_| {
+            let mut elements = vec![border("│")];
+            for (cell, &width) in cells.iter_mut().zip(widths.iter()) {
+                elements.push(LayoutElement::Whitespace(1));
+                match cell.next() {
+                    Some(line) => elements.extend(line.elements),
+                    None => elements.push(LayoutElement::Whitespace(width)),
+                }
+                elements.push(LayoutElement::Whitespace(1));
+                elements.push(border("│"));
+            }
+            elements
+        })
+        .collect()
+}
+
+/// When the screen is too narrow to fit even a single column per cell, every row is shown
+/// as a list of header-value pairs instead, separated by horizontal rules.
+fn calculate_stacked_layout<'a>(screen_width: usize, table: &Table<'a>) -> Vec<LayoutLine<'a>> {
+    let mut lines = Vec::new();
+    for (i, row) in table.rows.iter().enumerate() {
+        if i != 0 {
+            lines.push(LayoutLine {
+                elements: (0..screen_width).map(|_| border("─")).collect(),
+            });
+        }
+        for (header, cell) in table.header.iter().zip(row.iter()) {
+            let mut words = emphasize(header);
+            words.extend_from_slice(cell);
+            lines.extend(layout::calculate_aligned_layout(
+                screen_width,
+                &words,
+                ColumnAlignment::Left,
+            ));
+        }
+    }
+    lines
+}
+
+pub fn calculate_table_layout<'a>(screen_width: usize, table: &Table<'a>) -> Vec<LayoutLine<'a>> {
+    let columns = table.alignments.len();
+    let decoration_width = columns * COLUMN_DECORATION_WIDTH + 1;
+    if screen_width < decoration_width + columns {
+        return calculate_stacked_layout(screen_width, table);
+    }
+
+    let widths = distribute_widths(
+        screen_width - decoration_width,
+        &column_extents(table, cell_min_width),
+        &column_extents(table, cell_max_width),
+    );
+    let table_width: usize = widths.iter().sum::<usize>() + decoration_width;
+
+    let header: Vec<TableCell<'a>> = table.header.iter().map(|cell| emphasize(cell)).collect();
+    let mut lines = vec![rule(&widths, "┌", "┬", "┐")];
+    lines.extend(row_lines(&header, &widths, &table.alignments));
+    lines.push(rule(&widths, "├", "┼", "┤"));
+    for row in table.rows.iter() {
+        lines.extend(row_lines(row, &widths, &table.alignments));
+    }
+    lines.push(rule(&widths, "└", "┴", "┘"));
+
+    lines
+        .into_iter()
+        .map(|mut elements| {
+            if table_width < screen_width {
+                elements.push(LayoutElement::Whitespace(screen_width - table_width));
+            }
+            LayoutLine { elements }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::layout::tests::debug_print;
+
+    fn line_width(line: &LayoutLine) -> usize {
+        line.elements
+            .iter()
+            .map(|element| match element {
+                LayoutElement::Word(word) => layout::styled_word_length(word),
+                &LayoutElement::Whitespace(n) => n,
+            })
+            .sum()
+    }
+
+    fn cell(text: &str) -> TableCell<'_> {
+        text.split_ascii_whitespace()
+            .map(|word| word.into())
+            .collect()
+    }
+
+    fn test_table() -> Table<'static> {
+        Table {
+            alignments: vec![
+                ColumnAlignment::Left,
+                ColumnAlignment::Center,
+                ColumnAlignment::Right,
+            ],
+            header: vec![cell("Name"), cell("Description"), cell("Price")],
+            rows: vec![
+                vec![
+                    cell("Apple"),
+                    cell("A round fruit that keeps the doctor away"),
+                    cell("1.20"),
+                ],
+                vec![cell("Watermelon"), cell(""), cell("4.99")],
+            ],
+        }
+    }
+
+    #[test]
+    fn widths_fit_content_when_possible() {
+        assert_eq!(
+            distribute_widths(100, &[1, 2, 3], &[10, 20, 30]),
+            vec![10, 20, 30]
+        );
+        assert_eq!(
+            distribute_widths(60, &[1, 2, 3], &[10, 20, 30]),
+            vec![10, 20, 30]
+        );
+    }
+
+    #[test]
+    fn widths_are_distributed_proportionally() {
+        let widths = distribute_widths(30, &[5, 5, 5], &[10, 30, 5]);
+
+        assert_eq!(widths.iter().sum::<usize>(), 30);
+        assert_eq!(widths[2], 5);
+        assert!(widths[1] > widths[0]);
+        assert!(widths[0] >= 5);
+    }
+
+    #[test]
+    fn widths_shrink_below_longest_words() {
+        let widths = distribute_widths(6, &[10, 10, 10], &[20, 20, 20]);
+
+        assert_eq!(widths, vec![2, 2, 2]);
+        assert_eq!(
+            distribute_widths(3, &[1, 1, 10], &[1, 1, 10]),
+            vec![1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn table_lines_fill_screen_width() {
+        let table = test_table();
+        for screen_width in 1..=100 {
+            let layout = calculate_table_layout(screen_width, &table);
+            for line in layout.iter() {
+                if line_width(line) != screen_width {
+                    debug_print(&layout);
+                    panic!(
+                        "Line width not equal to screen width ({}), see debug output",
+                        screen_width
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_screens_stack_cells() {
+        let table = test_table();
+
+        let layout = calculate_table_layout(12, &table);
+
+        assert!(layout
+            .iter()
+            .all(|line| !line.elements.contains(&border("│"))));
+    }
+}
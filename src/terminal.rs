@@ -1,14 +1,12 @@
 use crossterm::{
-    cursor::{Hide, MoveTo},
+    cursor::{Hide, MoveTo, Show},
     event::{self, Event, KeyCode, KeyModifiers},
-    style::{
-        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
-    },
+    style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
     terminal, ErrorKind, QueueableCommand,
 };
 use std::io::{self, Stdout, Write};
 use std::{error::Error, fmt::Display};
-use terminal::{Clear, ClearType};
+use terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 
 use crate::common::{Dimensions, Position};
 
@@ -46,13 +44,16 @@ impl Display for TerminalError {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct AnsiColor(pub u8);
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Style {
     pub foregound: Option<AnsiColor>,
     pub background: Option<AnsiColor>,
     pub bold: bool,
     pub italic: bool,
+    pub underline: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -79,6 +80,13 @@ pub enum TerminalEvent {
 
 pub struct TerminalCanvas {
     stdout: Stdout,
+    hyperlinks: bool,
+    colors: bool,
+    styles: bool,
+    // The style that the terminal prints text in now
+    style: Style,
+    // Where the text that the terminal prints now links to
+    hyperlink: Option<String>,
 }
 
 impl TerminalCanvas {
@@ -90,10 +98,6 @@ impl TerminalCanvas {
         })
     }
 
-    pub fn width(&self) -> TerminalResult<usize> {
-        Ok(self.dimensions()?.width)
-    }
-
     pub fn height(&self) -> TerminalResult<usize> {
         Ok(self.dimensions()?.height)
     }
@@ -104,12 +108,28 @@ impl TerminalCanvas {
     }
 
     pub fn set_style(&mut self, style: &Style) -> TerminalResult<()> {
-        self.stdout.queue(ResetColor)?;
-        if let Some(ref fg) = style.foregound {
+        if !self.styles {
+            return Ok(());
+        }
+        let style = if self.colors {
+            *style
+        } else {
+            Style {
+                foregound: None,
+                background: None,
+                ..*style
+            }
+        };
+        if style == self.style {
+            return Ok(());
+        }
+        self.style = style;
+        self.stdout.queue(SetAttribute(Attribute::Reset))?;
+        if let Some(fg) = style.foregound {
             self.stdout
                 .queue(SetForegroundColor(Color::AnsiValue(fg.0)))?;
         }
-        if let Some(ref bg) = style.background {
+        if let Some(bg) = style.background {
             self.stdout
                 .queue(SetBackgroundColor(Color::AnsiValue(bg.0)))?;
         }
@@ -119,6 +139,9 @@ impl TerminalCanvas {
         if style.italic {
             self.stdout.queue(SetAttribute(Attribute::Italic))?;
         }
+        if style.underline {
+            self.stdout.queue(SetAttribute(Attribute::Underlined))?;
+        }
         Ok(())
     }
 
@@ -127,10 +150,47 @@ impl TerminalCanvas {
         Ok(())
     }
 
-    pub fn print(&mut self, pos: &Position, c: char) -> TerminalResult<()> {
-        self.stdout
-            .queue(MoveTo(pos.x as u16, pos.y as u16))?
-            .queue(Print(c))?;
+    /// Without colors, text is still printed in bold, italic or underlined.
+    pub fn set_colors(&mut self, enabled: bool) {
+        self.colors = enabled;
+    }
+
+    /// Without styles, text is printed as it is, without any escape sequences for colors,
+    /// attributes or hyperlinks.
+    pub fn set_styles(&mut self, enabled: bool) {
+        self.styles = enabled;
+        self.hyperlinks &= enabled;
+    }
+
+    /// Whether spaces with a background color can be seen.
+    pub fn shows_backgrounds(&self) -> bool {
+        self.styles && self.colors
+    }
+
+    /// Some terminals print OSC 8 sequences as garbage instead of ignoring them, so
+    /// hyperlinks can be turned off.
+    pub fn set_hyperlinks(&mut self, enabled: bool) {
+        self.hyperlinks = enabled;
+    }
+
+    /// Text printed after this can be clicked to open `destination`, until the link is set
+    /// to `None`. The escape sequences around the text take up no space on the screen.
+    pub fn set_hyperlink(&mut self, destination: Option<&str>) -> TerminalResult<()> {
+        if !self.hyperlinks || self.hyperlink.as_deref() == destination {
+            return Ok(());
+        }
+        if self.hyperlink.is_some() {
+            self.stdout.queue(Print(HYPERLINK_END))?;
+        }
+        if let Some(destination) = destination {
+            self.stdout.queue(Print(hyperlink_start(destination)))?;
+        }
+        self.hyperlink = destination.map(str::to_string);
+        Ok(())
+    }
+
+    pub fn move_to(&mut self, pos: &Position) -> TerminalResult<()> {
+        self.stdout.queue(MoveTo(pos.x as u16, pos.y as u16))?;
         Ok(())
     }
 
@@ -140,6 +200,21 @@ impl TerminalCanvas {
     }
 }
 
+const HYPERLINK_END: &str = "\x1b]8;;\x1b\\";
+
+fn hyperlink_start(destination: &str) -> String {
+    // Only printable ASCII is allowed in the URI
+    let mut uri = String::with_capacity(destination.len());
+    for b in destination.bytes() {
+        if b.is_ascii_graphic() {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    format!("\x1b]8;;{}\x1b\\", uri)
+}
+
 pub struct TerminalEvents;
 
 impl TerminalEvents {
@@ -167,22 +242,46 @@ impl TerminalEvents {
     }
 }
 
+/// Takes over the screen until `exit` is called, which brings back whatever was on it.
 pub fn start_in_raw_mode() -> TerminalResult<(TerminalCanvas, TerminalEvents)> {
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
-    stdout.queue(Hide)?.flush()?;
+    stdout.queue(EnterAlternateScreen)?.queue(Hide)?.flush()?;
     start()
 }
 
 pub fn start() -> TerminalResult<(TerminalCanvas, TerminalEvents)> {
+    // The Linux console doesn't know about OSC 8 and shows the sequences as text
+    let hyperlinks = !matches!(std::env::var("TERM").as_deref(), Ok("linux") | Ok("dumb"));
     Ok((
         TerminalCanvas {
             stdout: io::stdout(),
+            hyperlinks,
+            colors: true,
+            styles: true,
+            style: Style::default(),
+            hyperlink: None,
         },
         TerminalEvents,
     ))
 }
 
-pub fn exit() {
-    terminal::disable_raw_mode().unwrap();
+pub fn exit() -> TerminalResult<()> {
+    let mut stdout = io::stdout();
+    stdout.queue(Show)?.queue(LeaveAlternateScreen)?.flush()?;
+    terminal::disable_raw_mode()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hyperlink_uris_are_escaped() {
+        assert_eq!(
+            hyperlink_start("https://example.com/a b/ü"),
+            "\x1b]8;;https://example.com/a%20b/%C3%BC\x1b\\"
+        );
+    }
 }